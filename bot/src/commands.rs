@@ -1,18 +1,32 @@
 use std::process::Output;
 use std::time::Duration;
 
+use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::ensure;
 use anyhow::Context;
 use human_size::Byte;
+use human_size::Kilobyte;
 use human_size::Megabyte;
 use human_size::SpecificSize;
 use ipc2_host::workerset::TimeoutAction;
 use itertools::Itertools;
 use poise::samples::HelpConfiguration;
+use poise::serenity_prelude::Channel;
+use poise::serenity_prelude::ChannelType;
+use poise::serenity_prelude::GuildChannel;
+use poise::serenity_prelude::InteractionResponseType;
 use poise::CodeBlock;
 use shared::ClientMessage;
+use shared::EngineKind;
+use shared::ErrorKind;
+use shared::EvalError;
+use shared::EvalOptions;
+use shared::EvalOutput;
+use shared::EvalStats;
+use shared::Feature;
 use shared::HostMessage;
+use shared::SessionId;
 use std::fmt::Write;
 use sublime_fuzzy::best_match;
 use sysinfo::CpuExt;
@@ -23,10 +37,15 @@ use tracing::error;
 
 use crate::godbolt;
 use crate::godbolt::languages::Rust;
+use crate::godbolt::languages::CompileTarget;
+use crate::godbolt::languages::Cpp;
 use crate::godbolt::languages::C;
 use crate::godbolt::GodboltResponse;
+use crate::godbolt::RemarkKind;
 use crate::playground;
+use crate::pool::TimedOut;
 use crate::state::State;
+use crate::typescript;
 use crate::util;
 use crate::util::codeblock;
 use crate::util::CodeBlockOrRest;
@@ -112,13 +131,85 @@ pub async fn godbolt(
     flags: Option<MaybeQuoted>,
     block: CodeBlockOrRest,
 ) -> anyhow::Result<()> {
+    let reqwest = &cx.data().reqwest;
+    let block = CodeBlock::from(block);
+    let flags = flags.map(|q| q.value).filter(|f| !f.is_empty());
+    let (response, link) = tokio::join!(
+        compile_any_lang(reqwest, block.clone(), flags.clone()),
+        short_link_any_lang(reqwest, &block, flags.as_deref())
+    );
+
+    let mut output = util::codeblock_with_lang("x86asm", &response?.0);
+    if let Ok(link) = link {
+        let _ = write!(output, "\n<{link}>");
+    }
+    reply(&cx, output).await?;
+
+    Ok(())
+}
+
+async fn execute_c_family<T: CompileTarget>(
+    cx: &PoiseContext<'_>,
+    args: Option<MaybeQuoted>,
+    block: CodeBlock,
+    stdin: Option<CodeBlock>,
+) -> anyhow::Result<()> {
+    // compiler flags, then the program's arguments after `--`
+    let words: Vec<String> = args
+        .map(|q| q.value.split_whitespace().map(String::from).collect())
+        .unwrap_or_default();
+    let (flags, args) = match words.iter().position(|word| word == "--") {
+        Some(separator) => (
+            Some(words[..separator].join(" ")).filter(|flags| !flags.is_empty()),
+            words[separator + 1..].to_vec(),
+        ),
+        None => (None, words),
+    };
+    let stdin = stdin.map(|block| block.code).unwrap_or_default();
+
     let response =
-        compile_any_lang(&cx.data().reqwest, block.into(), flags.map(|q| q.value)).await?;
-    reply(&cx, util::codeblock_with_lang("x86asm", &response.0)).await?;
+        godbolt::execute::<T>(&cx.data().reqwest, block.code, flags, args, stdin).await?;
+
+    let output = util::codeblock_with_lang(T::CODEBLOCK_LANG, &response.output());
+    if !response.did_execute {
+        reply(cx, format!("Compiler error:\n{output}")).await?;
+    } else {
+        reply(cx, output).await?;
+    }
 
     Ok(())
 }
 
+/// Compiles and runs a C codeblock
+///
+/// Program arguments can be passed in quotes before the code, and compiler flags before a `--`
+/// in the same quotes, e.g. `"-O0 -lm -- foo bar"`.
+/// An optional second codeblock is passed to the program as stdin.
+#[poise::command(prefix_command, track_edits, broadcast_typing)]
+pub async fn c(
+    cx: PoiseContext<'_>,
+    args: Option<MaybeQuoted>,
+    block: CodeBlock,
+    stdin: Option<CodeBlock>,
+) -> anyhow::Result<()> {
+    execute_c_family::<C>(&cx, args, block, stdin).await
+}
+
+/// Compiles and runs a C++ codeblock
+///
+/// Program arguments can be passed in quotes before the code, and compiler flags before a `--`
+/// in the same quotes, e.g. `"-O0 -- foo bar"`.
+/// An optional second codeblock is passed to the program as stdin.
+#[poise::command(prefix_command, track_edits, broadcast_typing)]
+pub async fn cpp(
+    cx: PoiseContext<'_>,
+    args: Option<MaybeQuoted>,
+    block: CodeBlock,
+    stdin: Option<CodeBlock>,
+) -> anyhow::Result<()> {
+    execute_c_family::<Cpp>(&cx, args, block, stdin).await
+}
+
 /// Help me
 #[poise::command(prefix_command, track_edits)]
 pub async fn help(cx: PoiseContext<'_>, command: Option<String>) -> anyhow::Result<()> {
@@ -140,22 +231,163 @@ async fn compile_any_lang(
     Ok(match language.as_deref() {
         Some("rs" | "rust") | None => godbolt::get_asm::<Rust>(reqwest, code, flags).await?,
         Some("c") => godbolt::get_asm::<C>(reqwest, code, flags).await?,
+        Some("cpp" | "c++") => godbolt::get_asm::<Cpp>(reqwest, code, flags).await?,
         Some(other) => bail!("unknown codeblock language: {other}"),
     })
 }
 
+async fn short_link_any_lang(
+    reqwest: &reqwest::Client,
+    CodeBlock { code, language }: &CodeBlock,
+    flags: Option<&str>,
+) -> anyhow::Result<String> {
+    match language.as_deref() {
+        Some("rs" | "rust") | None => godbolt::create_short_link::<Rust>(reqwest, code, flags).await,
+        Some("c") => godbolt::create_short_link::<C>(reqwest, code, flags).await,
+        Some("cpp" | "c++") => godbolt::create_short_link::<Cpp>(reqwest, code, flags).await,
+        Some(other) => bail!("unknown codeblock language: {other}"),
+    }
+}
+
 /// Compile a codeblock and get the assembly
 #[poise::command(prefix_command, track_edits)]
 pub async fn asm(cx: PoiseContext<'_>, blocks: Vec<CodeBlock>) -> anyhow::Result<()> {
     let mut output = String::new();
+    let mut mnemonics = Vec::new();
     let reqwest = &cx.data().reqwest;
 
     for block in blocks {
-        let out = compile_any_lang(reqwest, block, None).await?;
+        let (out, link) = tokio::join!(
+            compile_any_lang(reqwest, block.clone(), None),
+            short_link_any_lang(reqwest, &block, None)
+        );
+        let out = out?;
+        mnemonics.extend(godbolt::mnemonics(&out.0).into_iter().map(String::from));
         output.push_str(&util::codeblock_with_lang("x86asm", &out.0));
+        if let Ok(link) = link {
+            let _ = writeln!(output, "<{link}>");
+        }
     }
 
-    reply(&cx, output).await?;
+    // `asm` always compiles with the default flags
+    let instruction_set = godbolt::flags_instruction_set(None);
+    reply_with_instruction_menu(&cx, output, mnemonics, instruction_set).await?;
+    Ok(())
+}
+
+/// The instruction menu is removed once nobody used it for this long
+const INSTRUCTION_MENU_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Replies with a select menu below the message that looks up the selected instruction.
+/// The menu is handled in the background, and removed once nobody used it for a while
+async fn reply_with_instruction_menu(
+    cx: &PoiseContext<'_>,
+    content: String,
+    mnemonics: Vec<String>,
+    instruction_set: &'static str,
+) -> anyhow::Result<()> {
+    // Discord allows at most 25 options in a select menu
+    let mnemonics = mnemonics.into_iter().unique().take(25).collect::<Vec<_>>();
+    if mnemonics.is_empty() {
+        reply(cx, content).await?;
+        return Ok(());
+    }
+
+    let handle = cx
+        .send(|reply| {
+            reply
+                .allowed_mentions(|m| m.empty_parse())
+                .content(content)
+                .components(|c| {
+                    c.create_action_row(|row| {
+                        row.create_select_menu(|menu| {
+                            menu.custom_id("insn")
+                                .placeholder("Look up an instruction")
+                                .options(|options| {
+                                    for mnemonic in &mnemonics {
+                                        options.create_option(|o| o.label(mnemonic).value(mnemonic));
+                                    }
+                                    options
+                                })
+                        })
+                    })
+                })
+        })
+        .await?;
+
+    let mut message = handle.message().await?.into_owned();
+    let serenity = cx.serenity_context().clone();
+    let reqwest = cx.data().reqwest.clone();
+    tokio::spawn(async move {
+        while let Some(interaction) = message
+            .await_component_interaction(&serenity)
+            .timeout(INSTRUCTION_MENU_TIMEOUT)
+            .await
+        {
+            let Some(mnemonic) = interaction.data.values.first() else {
+                continue;
+            };
+
+            let content = lookup_instruction(&reqwest, instruction_set, mnemonic)
+                .await
+                .unwrap_or_else(|err| err.to_string());
+
+            let response = interaction
+                .create_interaction_response(&serenity, |response| {
+                    response
+                        .kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|data| {
+                            data.content(util::shrink_to_fit(&content)).ephemeral(true)
+                        })
+                })
+                .await;
+            if let Err(err) = response {
+                tracing::warn!(%err, "Failed to respond to an instruction lookup");
+            }
+        }
+
+        // so that the menu doesn't look like it still works
+        if let Err(err) = message.edit(&serenity, |m| m.components(|c| c)).await {
+            tracing::warn!(%err, "Failed to remove the instruction menu");
+        }
+    });
+
+    Ok(())
+}
+
+async fn lookup_instruction(
+    reqwest: &reqwest::Client,
+    instruction_set: &str,
+    mnemonic: &str,
+) -> anyhow::Result<String> {
+    let info = godbolt::get_instruction_info(reqwest, instruction_set, mnemonic).await?;
+
+    let mut output = format!("**{}**\n{}\n", mnemonic.to_ascii_uppercase(), info.tooltip);
+    let forms = info.operand_forms(mnemonic);
+    if !forms.is_empty() {
+        output.push_str(&util::codeblock_with_lang("x86asm", &forms.join("\n")));
+        output.push('\n');
+    }
+    let _ = write!(output, "<{}>", info.url);
+
+    Ok(output)
+}
+
+/// Looks up an assembly instruction
+///
+/// The architecture defaults to x86_64, but can also be arm or aarch64.
+#[poise::command(prefix_command, track_edits, broadcast_typing)]
+pub async fn insn(
+    cx: PoiseContext<'_>,
+    mnemonic: String,
+    arch: Option<String>,
+) -> anyhow::Result<()> {
+    let arch = arch.as_deref().unwrap_or("x86_64");
+    let instruction_set =
+        godbolt::instruction_set(arch).with_context(|| format!("unknown architecture: {arch}"))?;
+
+    let output = lookup_instruction(&cx.data().reqwest, instruction_set, &mnemonic).await?;
+    reply(&cx, util::shrink_to_fit(&output).into()).await?;
     Ok(())
 }
 
@@ -178,58 +410,786 @@ pub async fn asmdiff(
     Ok(())
 }
 
-const MAX_TIME: Duration = Duration::from_secs(5);
+const DEFAULT_REMARK_PASSES: &[&str] = &["loop-vectorize", "inline", "licm"];
+
+/// Shows LLVM optimization remarks for a codeblock, e.g. why a loop didn't vectorize
+///
+/// By default only remarks of the loop-vectorize, inline and licm passes are shown.
+/// A quoted, comma separated list of passes (or "all") can be passed to change that.
+#[poise::command(prefix_command, track_edits, broadcast_typing)]
+pub async fn remarks(
+    cx: PoiseContext<'_>,
+    passes: Option<MaybeQuoted>,
+    block: CodeBlockOrRest,
+) -> anyhow::Result<()> {
+    let reqwest = &cx.data().reqwest;
+    let passes = passes.map(|q| q.value).filter(|p| !p.is_empty());
+    let passes = match passes.as_deref() {
+        None => DEFAULT_REMARK_PASSES.to_vec(),
+        Some("all") => Vec::new(),
+        Some(passes) => passes.split(',').map(str::trim).collect(),
+    };
+
+    let CodeBlockOrRest { code, language } = block;
+    let source = code.clone();
+    let remarks = match language.as_deref() {
+        Some("rs" | "rust") | None => {
+            godbolt::get_opt_remarks::<Rust>(reqwest, code, None, &passes).await?
+        }
+        Some("c") => godbolt::get_opt_remarks::<C>(reqwest, code, None, &passes).await?,
+        Some("cpp" | "c++") => {
+            godbolt::get_opt_remarks::<Cpp>(reqwest, code, None, &passes).await?
+        }
+        Some(other) => bail!("unknown codeblock language: {other}"),
+    };
+
+    if remarks.is_empty() {
+        reply(&cx, "No optimization remarks for the selected passes".into()).await?;
+        return Ok(());
+    }
+
+    let lines = source.lines().collect::<Vec<_>>();
+    let mut output = String::new();
+    for (line, remarks) in &remarks.iter().group_by(|remark| remark.line) {
+        let source_line = (line as usize)
+            .checked_sub(1)
+            .and_then(|index| lines.get(index))
+            .map(|l| l.trim())
+            .unwrap_or_default();
+        let _ = writeln!(output, "{line:>3} | {source_line}");
+
+        for remark in remarks {
+            let kind = match remark.kind {
+                RemarkKind::Passed => "passed",
+                RemarkKind::Missed => "missed",
+                RemarkKind::Failure => "failed",
+                RemarkKind::Analysis => "analysis",
+            };
+            let _ = writeln!(output, "    = {kind} {}: {}", remark.pass, remark.message);
+        }
+    }
+
+    reply(&cx, util::codeblock(&output)).await?;
+    Ok(())
+}
+
+/// Shows how the LLVM IR of a function changes pass by pass
+///
+/// Only passes that changed something are shown, unless `all` is passed.
+#[poise::command(prefix_command, track_edits, broadcast_typing)]
+pub async fn pipeline(
+    cx: PoiseContext<'_>,
+    function: String,
+    #[flag] all: bool,
+    block: CodeBlockOrRest,
+) -> anyhow::Result<()> {
+    let reqwest = &cx.data().reqwest;
+    let CodeBlockOrRest { code, language } = block;
+    let passes = match language.as_deref() {
+        Some("rs" | "rust") | None => {
+            godbolt::get_opt_pipeline::<Rust>(reqwest, code, None, &function).await?
+        }
+        Some("c") => godbolt::get_opt_pipeline::<C>(reqwest, code, None, &function).await?,
+        Some("cpp" | "c++") => {
+            godbolt::get_opt_pipeline::<Cpp>(reqwest, code, None, &function).await?
+        }
+        Some(other) => bail!("unknown codeblock language: {other}"),
+    };
+
+    let mut output = String::new();
+    for pass in passes.iter().filter(|pass| all || pass.ir_changed) {
+        let _ = writeln!(output, "; {}", pass.name);
+        if pass.ir_changed {
+            output.push_str(&pass.diff());
+        }
+    }
+
+    if output.is_empty() {
+        output.push_str("; no pass changed the IR of this function");
+    }
+
+    reply(&cx, util::codeblock_with_lang("diff", &output)).await?;
+    Ok(())
+}
+
+/// How much of a reply can be used for console output, leaving room for the result
+const MAX_LOGS_LEN: usize = 1000;
 
 enum Mode {
     Ir,
+    Ast,
+    Tokens,
+    Bytecode,
+    IrDiff,
     Eval,
 }
 
+/// Whether the command was used in a thread.
+/// If the channel can't be looked up, it is treated as not being a thread
+async fn is_thread(cx: &PoiseContext<'_>) -> bool {
+    let channel_id = cx.channel_id();
+    let cached = cx.data().thread_channels.lock().unwrap().get(channel_id);
+    if let Some(is_thread) = cached {
+        return is_thread;
+    }
+
+    let channel = match channel_id.to_channel(cx.serenity_context()).await {
+        Ok(channel) => channel,
+        Err(err) => {
+            tracing::warn!(%err, %channel_id, "Failed to look up channel, assuming it is not a thread");
+            return false;
+        }
+    };
+    let is_thread = matches!(
+        channel,
+        Channel::Guild(GuildChannel {
+            kind: ChannelType::PublicThread | ChannelType::PrivateThread | ChannelType::NewsThread,
+            ..
+        })
+    );
+
+    cx.data()
+        .thread_channels
+        .lock()
+        .unwrap()
+        .insert(channel_id, is_thread);
+    is_thread
+}
+
 /// Executes JavaScript code
+///
+/// `-ir`, `-ast`, `-tokens` and `-bytecode` show how the engine compiles the code instead.
+/// `-irdiff` shows what the optimizer changed between two opt levels (`-O0 -O2` by default).
+/// `-color` colorizes the result.
+/// `-deterministic` seeds `Math.random` and freezes the clock, so the output can be reproduced.
+/// `-seed N` chooses the seed (0 by default) and implies `-deterministic`.
+/// `-stats` also shows how long parsing, compiling and running took, and the heap size.
+/// In threads, every message is evaluated in the same global scope, like a REPL.
+/// Outside of threads, `-session` does the same for your own messages.
+/// `,js reset` clears the session.
+/// TypeScript codeblocks are run with their types removed.
 #[poise::command(prefix_command, track_edits)]
 pub async fn js(
     cx: PoiseContext<'_>,
     flags: Option<MaybeQuoted>,
     block: CodeBlockOrRest,
 ) -> anyhow::Result<()> {
-    let CodeBlockOrRest { code, .. } = block;
+    let CodeBlockOrRest { code, language } = block;
     tracing::info!(%code, "Send JS code to worker");
 
+    // types are replaced with whitespace, so error spans point into the original source too
+    let typescript = matches!(language.as_deref(), Some("ts" | "typescript"));
+    let original = typescript.then(|| code.clone());
+    let code = match &original {
+        Some(original) => match typescript::strip_types(original) {
+            Ok(code) => code,
+            Err(err) => {
+                let excerpt = typescript::excerpt(original, &[err.span]);
+                reply(&cx, codeblock(&format!("{}\n{excerpt}", err.message))).await?;
+                return Ok(());
+            }
+        },
+        None => code,
+    };
+
     let mut opt = shared::Opt::Basic;
+    // all opt levels in the order they were passed, used by -irdiff
+    let mut opts = Vec::new();
     let mut mode = Mode::Eval;
+    let mut user_session = false;
+    let mut colors = false;
+    let mut seed = None;
+    let mut stats = false;
     let flags = flags.map(|v| v.value).unwrap_or_default();
-    for flag in flags.split_ascii_whitespace() {
+    let mut words = flags.split_ascii_whitespace();
+    while let Some(flag) = words.next() {
         if let Some(flag) = flag.strip_prefix('-') {
             match flag {
                 "O0" => opt = shared::Opt::None,
                 "O1" => opt = shared::Opt::Basic,
                 "O2" => opt = shared::Opt::Aggressive,
                 "ir" => mode = Mode::Ir,
+                "ast" => mode = Mode::Ast,
+                "tokens" => mode = Mode::Tokens,
+                "bytecode" => mode = Mode::Bytecode,
+                "irdiff" => mode = Mode::IrDiff,
+                "session" => user_session = true,
+                "color" => colors = true,
+                "stats" => stats = true,
+                "deterministic" => seed = seed.or(Some(0)),
+                "seed" => {
+                    let value = words.next().context("-seed needs a number")?;
+                    seed = Some(
+                        value
+                            .parse()
+                            .with_context(|| format!("invalid seed: {value}"))?,
+                    );
+                }
                 _ => bail!("unknown flag {flag}"),
             }
+
+            if flag.starts_with('O') {
+                opts.push(opt);
+            }
         }
     }
 
-    let ClientMessage::EvalResponse(message) = cx
+    let session = if is_thread(&cx).await {
+        Some(SessionId::Thread(cx.channel_id().0))
+    } else if user_session {
+        Some(SessionId::User(cx.author().id.0))
+    } else {
+        None
+    };
+
+    let worker_info = cx.data().worker_info.as_ref().map_err(|err| anyhow!("{err}"))?;
+    let feature = match (&mode, session) {
+        (Mode::Ir, _) => Feature::DumpIr,
+        (Mode::Ast, _) => Feature::DumpAst,
+        (Mode::Tokens, _) => Feature::DumpTokens,
+        (Mode::Bytecode, _) => Feature::DumpBytecode,
+        (Mode::IrDiff, _) => Feature::DumpIrDiff,
+        (Mode::Eval, Some(_)) => Feature::Sessions,
+        (Mode::Eval, None) => Feature::Eval,
+    };
+    ensure!(
+        worker_info.features.contains(&feature),
+        "the worker does not support {feature:?}"
+    );
+    ensure!(
+        worker_info.opt_levels.contains(&opt),
+        "the worker does not support {opt:?}"
+    );
+    ensure!(
+        !stats || worker_info.features.contains(&Feature::Stats),
+        "the worker does not support -stats"
+    );
+
+    if code.trim() == "reset" {
+        let session = session.context("there is no session here, use a thread or `-session`")?;
+        let workers = cx.data().sessions.shard(session);
+        workers
+            .send_timeout(
+                HostMessage::ResetSession(session),
+                workers.timeout(),
+                TimeoutAction::Restart,
+            )
+            .await?;
+        cx.data().sessions.forget(session);
+
+        reply(&cx, "Session cleared".into()).await?;
+        return Ok(());
+    }
+
+    // the same for every worker pool, including the session workers
+    let timeout = cx.data().workers.timeout();
+    let options = EvalOptions {
+        opt,
+        colors,
+        seed,
+        stats,
+        timeout,
+    };
+    // kept for crash reports
+    let source = code.clone();
+    let eval_session = session.filter(|_| matches!(mode, Mode::Eval));
+    let (workers, message) = match (mode, session) {
+        (Mode::Eval, Some(session)) => (
+            cx.data().sessions.shard(session),
+            HostMessage::EvalSession(session, code, options),
+        ),
+        (Mode::Eval, None) => (
+            &cx.data().workers,
+            HostMessage::Eval(EngineKind::Dash, code, options),
+        ),
+        (Mode::Ir, _) => (&cx.data().workers, HostMessage::DumpIr(code, opt)),
+        (Mode::Ast, _) => (&cx.data().workers, HostMessage::DumpAst(code)),
+        (Mode::Tokens, _) => (&cx.data().workers, HostMessage::DumpTokens(code)),
+        (Mode::Bytecode, _) => (&cx.data().workers, HostMessage::DumpBytecode(code, opt)),
+        (Mode::IrDiff, _) => {
+            let (left, right) = match opts[..] {
+                [left, right] => (left, right),
+                [] => (shared::Opt::None, shared::Opt::Aggressive),
+                _ => bail!("-irdiff needs exactly two opt levels, e.g. `-O0 -O2`"),
+            };
+            for opt in [left, right] {
+                ensure!(
+                    worker_info.opt_levels.contains(&opt),
+                    "the worker does not support {opt:?}"
+                );
+            }
+            (
+                &cx.data().workers,
+                HostMessage::DumpIrDiff(code, left, right),
+            )
+        }
+    };
+    let value_lang = match mode {
+        Mode::IrDiff => "diff",
+        Mode::Eval if colors => "ansi",
+        _ => "js",
+    };
+
+    let response = match workers
+        .send_timeout(message, timeout, TimeoutAction::Restart)
+        .await
+    {
+        Ok(response) => response,
+        // the worker was killed and restarted because it took too long
+        Err(err) if err.is::<TimedOut>() => {
+            if let Some(session) = eval_session {
+                cx.data().sessions.forget(session);
+                bail!("the code did not finish within {timeout:?}, so the session was reset")
+            }
+            bail!("the code did not finish within {timeout:?}")
+        }
+        // the worker process died on its own, e.g. from a stack overflow
+        // or from an allocation that went beyond what the worker can get
+        Err(err) => {
+            if let Some(session) = eval_session {
+                cx.data().sessions.forget(session);
+            }
+            let details = format!(
+                "the worker process exited without a response: {err}\n\
+                 (this also happens when the code allocates a lot more memory than the limit at once)"
+            );
+            reply(&cx, crash_report(&cx, &flags, &source, &details)).await?;
+            return Ok(());
+        }
+    };
+    let (mut output, lost_session) = match (response, eval_session) {
+        (ClientMessage::EvalResponse(output), None) => (output, false),
+        (
+            ClientMessage::SessionEvalResponse {
+                output,
+                new_session,
+            },
+            Some(session),
+        ) => (output, new_session && cx.data().sessions.created(session)),
+        _ => bail!("unexpected response from worker"),
+    };
+
+    if let Err(EvalError {
+        kind: ErrorKind::Crash,
+        message,
+        ..
+    }) = &output.result
+    {
+        // the worker dropped the session along with the crashed VM
+        if let Some(session) = eval_session {
+            cx.data().sessions.forget(session);
+        }
+        reply(&cx, crash_report(&cx, &flags, &source, message)).await?;
+        return Ok(());
+    }
+
+    // the worker's error shows the stripped source, so also show where it is in the TypeScript
+    if let (Some(original), Err(err)) = (&original, &mut output.result) {
+        if err.kind == ErrorKind::Syntax && !err.spans.is_empty() {
+            err.message.push_str("\nin the TypeScript source:\n");
+            err.message.push_str(&typescript::excerpt(original, &err.spans));
+        }
+    }
+
+    let header = if lost_session {
+        "note: your previous session is gone (it expired, hit a limit or its worker was restarted), \
+         this ran in a new one\n"
+    } else {
+        ""
+    };
+    reply(&cx, format_eval_output(header, output, value_lang)).await?;
+
+    Ok(())
+}
+
+/// Reply for when dash crashed, with a report that can be forwarded to dash's issue tracker as is.
+/// `details` is the panic message and backtrace if the worker caught the panic
+fn crash_report(cx: &PoiseContext<'_>, flags: &str, code: &str, details: &str) -> String {
+    let version = match &cx.data().worker_info {
+        Ok(info) => info.engine_version.as_str(),
+        Err(_) => "unknown",
+    };
+
+    let mut output =
+        String::from("the engine crashed while running your code (this is a dash bug)\n");
+    let _ = writeln!(output, "**dash version:** {version}");
+    if !flags.trim().is_empty() {
+        let flags = util::shrink_to(flags.trim(), 200);
+        let _ = writeln!(output, "**flags:** `{flags}`");
+    }
+    output.push_str("**code:**\n");
+    output.push_str(&util::codeblock_with_lang(
+        "js",
+        util::shrink_to(code.trim_end(), 600),
+    ));
+    output.push_str("\n**panic:**\n");
+    output.push_str(&util::codeblock_with_lang(
+        "",
+        util::shrink_to(details.trim_end(), 1800usize.saturating_sub(output.len())),
+    ));
+
+    output
+}
+
+/// Formats the output of an evaluation like node's REPL, after the header:
+/// everything that was logged first, then the completion value or error, then the stats if any
+fn format_eval_output(
+    header: &str,
+    EvalOutput {
+        result,
+        logs,
+        stats,
+        ..
+    }: EvalOutput,
+    value_lang: &str,
+) -> String {
+    let (lang, message) = match result {
+        Ok(value) => (value_lang, value),
+        Err(EvalError { kind, message, .. }) => match kind {
+            // syntax errors are already formatted with ANSI escape codes
+            ErrorKind::Syntax => ("ansi", message),
+            ErrorKind::Exception => (value_lang, format!("Uncaught {message}")),
+            ErrorKind::LimitExceeded => ("", message),
+            ErrorKind::Internal => ("", format!("internal error: {message}")),
+            ErrorKind::Crash => ("", format!("the engine crashed: {message}")),
+        },
+    };
+
+    let mut output = String::from(header);
+    if !logs.is_empty() {
+        // logged values are colorized together with the result
+        let logs_lang = if value_lang == "ansi" { "ansi" } else { "" };
+        output.push_str(&util::codeblock_with_lang(
+            logs_lang,
+            util::shrink_to(logs.trim_end(), MAX_LOGS_LEN),
+        ));
+        output.push('\n');
+    }
+    let stats = stats.map(|stats| codeblock(&format_eval_stats(&stats)));
+    let stats_len = stats.as_ref().map_or(0, String::len);
+    output.push_str(&util::codeblock_with_lang(
+        lang,
+        util::shrink_to(&message, 1980 - output.len() - stats_len),
+    ));
+    if let Some(stats) = stats {
+        output.push('\n');
+        output.push_str(&stats);
+    }
+
+    output
+}
+
+fn format_eval_stats(stats: &EvalStats) -> String {
+    let fmt_size = |bytes| {
+        SpecificSize::new(bytes as f64, Byte)
+            .unwrap()
+            .into::<Kilobyte>()
+    };
+
+    let mut output = format!(
+        "parse: {:?}, compile: {:?}, execute: {:?}\nheap: {:.1} -> {:.1}\n",
+        stats.parse,
+        stats.compile,
+        stats.execute,
+        fmt_size(stats.heap_before),
+        fmt_size(stats.heap_after)
+    );
+    let _ = match stats.gc_cycles {
+        Some(cycles) => writeln!(output, "GC cycles: {cycles}"),
+        None => writeln!(output, "GC cycles: not reported by the engine"),
+    };
+
+    output
+}
+
+const BENCH_MAX_TIME: Duration = Duration::from_secs(20);
+/// How long the worker may spend on the benchmarks. Below [`BENCH_MAX_TIME`], because a snippet
+/// is only stopped once the worker gets to check the time
+const BENCH_TIME_BUDGET: Duration = Duration::from_secs(15);
+
+/// Benchmarks two or more JavaScript codeblocks against each other
+///
+/// Every block is run repeatedly as the body of a function.
+/// `-O0`, `-O1` and `-O2` choose the opt level, `-opts` runs the blocks at all of them.
+#[poise::command(prefix_command, track_edits, broadcast_typing)]
+pub async fn jsbench(
+    cx: PoiseContext<'_>,
+    flags: Option<MaybeQuoted>,
+    blocks: Vec<CodeBlock>,
+) -> anyhow::Result<()> {
+    ensure!(blocks.len() >= 2, "need at least two codeblocks to compare");
+
+    let mut opts = vec![shared::Opt::Basic];
+    let flags = flags.map(|v| v.value).unwrap_or_default();
+    for flag in flags.split_ascii_whitespace() {
+        if let Some(flag) = flag.strip_prefix('-') {
+            match flag {
+                "O0" => opts = vec![shared::Opt::None],
+                "O1" => opts = vec![shared::Opt::Basic],
+                "O2" => opts = vec![shared::Opt::Aggressive],
+                "opts" => {
+                    opts = vec![shared::Opt::None, shared::Opt::Basic, shared::Opt::Aggressive]
+                }
+                _ => bail!("unknown flag {flag}"),
+            }
+        }
+    }
+
+    let worker_info = cx.data().worker_info.as_ref().map_err(|err| anyhow!("{err}"))?;
+    ensure!(
+        worker_info.features.contains(&Feature::Bench),
+        "the worker does not support benchmarks"
+    );
+
+    let snippets = blocks.into_iter().map(|block| block.code).collect();
+    let ClientMessage::BenchResponse(results) = cx
         .data()
         .workers
         .send_timeout(
-            match mode {
-                Mode::Eval => HostMessage::Eval(code, opt),
-                Mode::Ir => HostMessage::DumpIr(code, opt),
-            },
-            MAX_TIME,
+            HostMessage::Bench(snippets, opts, BENCH_TIME_BUDGET),
+            BENCH_MAX_TIME,
             TimeoutAction::Restart,
         )
-        .await?;
+        .await?
+    else {
+        bail!("unexpected response from worker");
+    };
+
+    let mut output = String::new();
+    for (opt, results) in &results.iter().group_by(|result| result.opt) {
+        let results = results.collect::<Vec<_>>();
+        let fastest = results
+            .iter()
+            .filter_map(|result| result.per_iteration.as_ref().ok())
+            .min()
+            .copied();
+
+        let _ = writeln!(output, "{opt:?}:");
+        for result in results {
+            let _ = match (&result.per_iteration, fastest) {
+                (Ok(time), Some(fastest)) => writeln!(
+                    output,
+                    "  #{}: {time:?}/iter ({} iterations{}), {:.2}x",
+                    result.snippet + 1,
+                    result.iterations,
+                    if result.partial {
+                        ", ran out of time"
+                    } else {
+                        ""
+                    },
+                    time.as_secs_f64() / fastest.as_secs_f64()
+                ),
+                (Ok(time), None) => writeln!(output, "  #{}: {time:?}/iter", result.snippet + 1),
+                // only the panic message, the backtrace would push the other results out
+                (Err(err), _) if err.kind == ErrorKind::Crash => writeln!(
+                    output,
+                    "  #{}: the engine crashed: {}",
+                    result.snippet + 1,
+                    err.message.split("\n\n").next().unwrap_or_default()
+                ),
+                (Err(err), _) => writeln!(
+                    output,
+                    "  #{}: {}",
+                    result.snippet + 1,
+                    util::strip_ansi(&err.message)
+                ),
+            };
+        }
+    }
 
-    let (lang, message) = match message {
-        Ok(message) => ("js", message),
-        Err(message) => ("ansi", message),
+    reply(&cx, codeblock(&output)).await?;
+    Ok(())
+}
+
+/// Executes Rhai code
+#[poise::command(prefix_command, track_edits)]
+pub async fn rhai(cx: PoiseContext<'_>, block: CodeBlockOrRest) -> anyhow::Result<()> {
+    let CodeBlockOrRest { code, .. } = block;
+
+    let worker_info = cx.data().worker_info.as_ref().map_err(|err| anyhow!("{err}"))?;
+    ensure!(
+        worker_info.engines.contains(&EngineKind::Rhai),
+        "the worker does not support Rhai"
+    );
+
+    let options = EvalOptions {
+        opt: shared::Opt::Basic,
+        colors: false,
+        seed: None,
+        stats: false,
+        timeout: cx.data().workers.timeout(),
+    };
+    let ClientMessage::EvalResponse(output) = cx
+        .data()
+        .workers
+        .send_timeout(
+            HostMessage::Eval(EngineKind::Rhai, code, options),
+            cx.data().workers.timeout(),
+            TimeoutAction::Restart,
+        )
+        .await?
+    else {
+        bail!("unexpected response from worker");
+    };
+
+    reply(&cx, format_eval_output("", output, "rs")).await?;
+    Ok(())
+}
+
+/// Evaluates JavaScript in both dash and boa and shows where they disagree
+///
+/// The formatted result, the thrown error and everything logged to the console are compared.
+/// `-O0`, `-O1` and `-O2` choose dash's opt level.
+/// Both engines run deterministically with the same seed, so `Math.random` and `Date` agree.
+#[poise::command(prefix_command, track_edits, broadcast_typing)]
+pub async fn jsdiff(
+    cx: PoiseContext<'_>,
+    flags: Option<MaybeQuoted>,
+    block: CodeBlockOrRest,
+) -> anyhow::Result<()> {
+    let CodeBlockOrRest { code, .. } = block;
+
+    let mut opt = shared::Opt::Basic;
+    let flags = flags.map(|v| v.value).unwrap_or_default();
+    for flag in flags.split_ascii_whitespace() {
+        if let Some(flag) = flag.strip_prefix('-') {
+            match flag {
+                "O0" => opt = shared::Opt::None,
+                "O1" => opt = shared::Opt::Basic,
+                "O2" => opt = shared::Opt::Aggressive,
+                _ => bail!("unknown flag {flag}"),
+            }
+        }
+    }
+
+    let worker_info = cx.data().worker_info.as_ref().map_err(|err| anyhow!("{err}"))?;
+    ensure!(
+        worker_info.engines.contains(&EngineKind::Boa),
+        "the worker does not support boa"
+    );
+
+    let workers = &cx.data().workers;
+    let options = EvalOptions {
+        opt,
+        colors: false,
+        seed: Some(0),
+        stats: false,
+        timeout: workers.timeout(),
+    };
+    let (dash, boa) = tokio::join!(
+        workers.send_timeout(
+            HostMessage::Eval(EngineKind::Dash, code.clone(), options),
+            workers.timeout(),
+            TimeoutAction::Restart,
+        ),
+        workers.send_timeout(
+            HostMessage::Eval(EngineKind::Boa, code, options),
+            workers.timeout(),
+            TimeoutAction::Restart,
+        )
+    );
+    let (ClientMessage::EvalResponse(dash), ClientMessage::EvalResponse(boa)) = (dash?, boa?) else {
+        bail!("unexpected response from worker");
     };
 
-    reply(&cx, util::codeblock_with_lang(lang, &message)).await?;
+    let mismatches = eval_mismatches(&dash, &boa);
+    if mismatches.is_empty() {
+        reply(&cx, format_eval_output("dash and boa agree\n", dash, "js")).await?;
+        return Ok(());
+    }
+
+    let mut output = String::from("dash (-) and boa (+) disagree\n");
+    for (what, left, right) in mismatches {
+        let _ = writeln!(output, "{what}:");
+        // there are at most two diffs, and both have to fit into one message
+        output.push_str(&util::codeblock_with_lang(
+            "diff",
+            util::shrink_to(&shared::diff_lines(&left, &right), 900),
+        ));
+        output.push('\n');
+    }
+
+    reply(&cx, output).await?;
+    Ok(())
+}
+
+/// Returns what differs between two evaluations of the same code, as `(what, dash, boa)`
+fn eval_mismatches(dash: &EvalOutput, boa: &EvalOutput) -> Vec<(&'static str, String, String)> {
+    fn summary(result: &Result<String, EvalError>) -> String {
+        match result {
+            Ok(value) => value.clone(),
+            // the engines word their syntax errors differently
+            Err(EvalError {
+                kind: ErrorKind::Syntax,
+                ..
+            }) => "SyntaxError".into(),
+            // stack traces are formatted differently, so only compare the first line
+            Err(EvalError {
+                kind: ErrorKind::Exception,
+                message,
+                ..
+            }) => format!("Uncaught {}", message.lines().next().unwrap_or_default()),
+            Err(EvalError { kind, message, .. }) => format!("{kind:?}: {message}"),
+        }
+    }
+
+    let mut mismatches = Vec::new();
+
+    let (left, right) = (summary(&dash.result), summary(&boa.result));
+    if left != right {
+        mismatches.push(("result", left, right));
+    }
+
+    if dash.logs.trim_end() != boa.logs.trim_end() {
+        mismatches.push(("console output", dash.logs.clone(), boa.logs.clone()));
+    }
+
+    mismatches
+}
+
+/// Runs a WebAssembly module, written in WAT or as a base64 encoded binary
+///
+/// `,wasm "add 1 2"` calls the exported function `add` with the arguments 1 and 2.
+/// Without a quoted call, `main` is called without arguments. Imports are not supported.
+#[poise::command(prefix_command, track_edits)]
+pub async fn wasm(
+    cx: PoiseContext<'_>,
+    call: Option<MaybeQuoted>,
+    block: CodeBlockOrRest,
+) -> anyhow::Result<()> {
+    let CodeBlockOrRest { code, .. } = block;
+
+    let call = call.map(|v| v.value).unwrap_or_default();
+    let mut words = call.split_ascii_whitespace().map(String::from);
+    let function = words.next().unwrap_or_else(|| "main".into());
+    let args = words.collect();
+
+    let worker_info = cx.data().worker_info.as_ref().map_err(|err| anyhow!("{err}"))?;
+    ensure!(
+        worker_info.features.contains(&Feature::Wasm),
+        "the worker does not support WebAssembly"
+    );
+
+    let ClientMessage::EvalResponse(output) = cx
+        .data()
+        .workers
+        .send_timeout(
+            HostMessage::EvalWasm(code, function, args),
+            cx.data().workers.timeout(),
+            TimeoutAction::Restart,
+        )
+        .await?
+    else {
+        bail!("unexpected response from worker");
+    };
+
+    // there is no exception value to format, traps and invalid modules are plain messages
+    let message = match output.result {
+        Ok(results) => results,
+        Err(EvalError { message, .. }) => message,
+    };
 
+    reply(&cx, codeblock(&message)).await?;
     Ok(())
 }
 
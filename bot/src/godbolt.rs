@@ -1,5 +1,8 @@
+use anyhow::Context;
+use itertools::Itertools;
 use reqwest::Client;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fmt::Write;
 
 use crate::util;
@@ -18,29 +21,32 @@ impl GodboltResponse {
     pub fn diff(self, other: Self) -> String {
         let Self(this) = self;
         let Self(other) = other;
-        let mut output = String::new();
-
-        for diff in diff::lines(this.trim_end(), other.trim_end()) {
-            let _ = match diff {
-                diff::Result::Left(l) => writeln!(output, "- {l}"),
-                diff::Result::Both(l, _) => writeln!(output, "  {l}"),
-                diff::Result::Right(r) => writeln!(output, "+ {r}"),
-            };
-        }
-
-        output
+        shared::diff_lines(&this, &other)
     }
 }
+
 pub mod languages {
     use serde_json::json;
 
     pub trait CompileTarget {
+        /// Flags used when the user didn't pass any
+        const DEFAULT_FLAGS: &'static str;
+        /// Tag of codeblocks with code of this language
+        const CODEBLOCK_LANG: &'static str;
+
         fn url() -> &'static str;
         fn prepare_json_body(source: &str, flags: Option<&str>) -> serde_json::Value;
+        /// Compiler flags that make the compiler emit optimization remarks for the given passes
+        fn remark_flags(passes: &[&str]) -> String;
+        fn prepare_shortlink_body(source: &str, flags: Option<&str>) -> serde_json::Value;
     }
 
     pub struct Rust;
     impl CompileTarget for Rust {
+        const DEFAULT_FLAGS: &'static str =
+            "-Copt-level=3 -Clto=on -Ctarget-feature=+sse3,+avx -Ctarget-cpu=native";
+        const CODEBLOCK_LANG: &'static str = "rs";
+
         fn url() -> &'static str {
             "https://godbolt.org/api/compiler/nightly/compile"
         }
@@ -49,16 +55,40 @@ pub mod languages {
                 "source": source,
                 "compiler": "nightly",
                 "options": {
-                    "userArguments": flags.unwrap_or("-Copt-level=3 -Clto=on -Ctarget-feature=+sse3,+avx -Ctarget-cpu=native")
+                    "userArguments": flags.unwrap_or(Self::DEFAULT_FLAGS)
                 },
                 "lang": "rust",
                 "allowStoreCodeDebug": true
             })
         }
+        fn remark_flags(passes: &[&str]) -> String {
+            let passes = if passes.is_empty() {
+                "all".into()
+            } else {
+                passes.join(",")
+            };
+            format!("-Cremark={passes} -Cdebuginfo=1")
+        }
+        fn prepare_shortlink_body(source: &str, flags: Option<&str>) -> serde_json::Value {
+            json!({
+                "sessions": [{
+                    "id": 1,
+                    "language": "rust",
+                    "source": source,
+                    "compilers": [{
+                        "id": "nightly",
+                        "options": flags.unwrap_or(Self::DEFAULT_FLAGS)
+                    }]
+                }]
+            })
+        }
     }
 
     pub struct C;
     impl CompileTarget for C {
+        const DEFAULT_FLAGS: &'static str = "-O3 -march=native";
+        const CODEBLOCK_LANG: &'static str = "c";
+
         fn url() -> &'static str {
             "https://godbolt.org/api/compiler/cclang1600/compile"
         }
@@ -67,12 +97,66 @@ pub mod languages {
                 "source": source,
                 "compiler": "cclang1600",
                 "options": {
-                    "userArguments": flags.unwrap_or("-O3 -march=native")
+                    "userArguments": flags.unwrap_or(Self::DEFAULT_FLAGS)
                 },
                 "lang": "c",
                 "allowStoreCodeDebug": true
             })
         }
+        fn remark_flags(_: &[&str]) -> String {
+            // clang remarks are collected through godbolt's `produceOptInfo` and filtered afterwards
+            "-g".into()
+        }
+        fn prepare_shortlink_body(source: &str, flags: Option<&str>) -> serde_json::Value {
+            json!({
+                "sessions": [{
+                    "id": 1,
+                    "language": "c",
+                    "source": source,
+                    "compilers": [{
+                        "id": "cclang1600",
+                        "options": flags.unwrap_or(Self::DEFAULT_FLAGS)
+                    }]
+                }]
+            })
+        }
+    }
+
+    pub struct Cpp;
+    impl CompileTarget for Cpp {
+        const DEFAULT_FLAGS: &'static str = "-O3 -march=native -std=c++20";
+        const CODEBLOCK_LANG: &'static str = "cpp";
+
+        fn url() -> &'static str {
+            "https://godbolt.org/api/compiler/clang1600/compile"
+        }
+        fn prepare_json_body(source: &str, flags: Option<&str>) -> serde_json::Value {
+            json!({
+                "source": source,
+                "compiler": "clang1600",
+                "options": {
+                    "userArguments": flags.unwrap_or(Self::DEFAULT_FLAGS)
+                },
+                "lang": "c++",
+                "allowStoreCodeDebug": true
+            })
+        }
+        fn remark_flags(_: &[&str]) -> String {
+            "-g".into()
+        }
+        fn prepare_shortlink_body(source: &str, flags: Option<&str>) -> serde_json::Value {
+            json!({
+                "sessions": [{
+                    "id": 1,
+                    "language": "c++",
+                    "source": source,
+                    "compilers": [{
+                        "id": "clang1600",
+                        "options": flags.unwrap_or(Self::DEFAULT_FLAGS)
+                    }]
+                }]
+            })
+        }
     }
 }
 
@@ -96,3 +180,477 @@ pub async fn get_asm<T: CompileTarget>(
 
     Ok(GodboltResponse(response))
 }
+
+#[derive(Deserialize)]
+struct GodboltDebugLoc {
+    #[serde(rename = "File")]
+    file: String,
+    #[serde(rename = "Line")]
+    line: u32,
+}
+
+#[derive(Deserialize)]
+struct GodboltOptRecord {
+    #[serde(rename = "Pass")]
+    pass: String,
+    #[serde(rename = "DebugLoc")]
+    debug_loc: Option<GodboltDebugLoc>,
+    #[serde(rename = "optType")]
+    opt_type: String,
+    #[serde(rename = "displayString")]
+    display_string: String,
+}
+
+#[derive(Deserialize)]
+struct GodboltJsonResponse {
+    #[serde(default)]
+    stderr: Vec<GodboltAsmBlock>,
+    #[serde(rename = "optOutput", default)]
+    opt_output: Vec<GodboltOptRecord>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemarkKind {
+    Passed,
+    Missed,
+    /// The pass failed, as opposed to deciding against an optimization
+    Failure,
+    Analysis,
+}
+
+#[derive(Debug)]
+pub struct OptRemark {
+    pub kind: RemarkKind,
+    pub pass: String,
+    pub line: u32,
+    pub message: String,
+}
+
+/// godbolt always names the input file `example.<ext>` (or `<source>` after filtering).
+/// Remarks pointing anywhere else come from inlined library code.
+fn is_user_source(file: &str) -> bool {
+    file == "<source>"
+        || file
+            .rsplit('/')
+            .next()
+            .is_some_and(|name| name.starts_with("example."))
+}
+
+/// Parses a remark that rustc emits as a diagnostic, e.g.
+/// `note: /app/example.rs:4:5 loop-vectorize (missed): loop not vectorized`
+fn parse_rustc_remark(line: &str) -> Option<OptRemark> {
+    let rest = line.trim_start().strip_prefix("note: ")?;
+    let (location, rest) = rest.split_once(' ')?;
+
+    // <file>:<line>:<column>
+    let mut parts = location.rsplitn(3, ':');
+    let _column = parts.next()?;
+    let line = parts.next()?.parse().ok()?;
+    let file = parts.next()?;
+
+    // <pass> (<kind>): <message>
+    let (pass, rest) = rest.split_once(" (")?;
+    let (kind, message) = rest.split_once("):")?;
+    let kind = match kind {
+        "success" => RemarkKind::Passed,
+        "missed" => RemarkKind::Missed,
+        "failure" => RemarkKind::Failure,
+        "analysis" => RemarkKind::Analysis,
+        _ => return None,
+    };
+
+    is_user_source(file).then(|| OptRemark {
+        kind,
+        pass: pass.to_owned(),
+        line,
+        message: message.trim().to_owned(),
+    })
+}
+
+/// Compiles the input with optimization remarks enabled and returns all remarks that point into the input,
+/// optionally filtered to the given passes.
+pub async fn get_opt_remarks<T: CompileTarget>(
+    client: &Client,
+    input: String,
+    flags: Option<String>,
+    passes: &[&str],
+) -> anyhow::Result<Vec<OptRemark>> {
+    let mut body = T::prepare_json_body(&input, flags.as_deref());
+    let user_args = body["options"]["userArguments"]
+        .as_str()
+        .unwrap_or_default()
+        .to_owned();
+    body["options"]["userArguments"] = format!("{user_args} {}", T::remark_flags(passes)).into();
+    body["options"]["compilerOptions"] = serde_json::json!({ "produceOptInfo": true });
+
+    let response: GodboltJsonResponse = client
+        .post(T::url())
+        .header("Accept", "application/json")
+        .json(&body)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let from_records = response.opt_output.into_iter().filter_map(|record| {
+        let loc = record.debug_loc?;
+        if !is_user_source(&loc.file) {
+            return None;
+        }
+
+        let kind = match record.opt_type.as_str() {
+            "Passed" => RemarkKind::Passed,
+            "Missed" => RemarkKind::Missed,
+            "Failure" => RemarkKind::Failure,
+            _ => RemarkKind::Analysis,
+        };
+
+        Some(OptRemark {
+            kind,
+            pass: record.pass,
+            line: loc.line,
+            message: record.display_string,
+        })
+    });
+
+    let from_stderr = response
+        .stderr
+        .iter()
+        .filter_map(|line| parse_rustc_remark(&util::strip_ansi(&line.text)));
+
+    let mut remarks = from_records
+        .chain(from_stderr)
+        .filter(|remark| passes.is_empty() || passes.contains(&remark.pass.as_str()))
+        .collect::<Vec<_>>();
+
+    remarks.sort_by_key(|remark| remark.line);
+    Ok(remarks)
+}
+
+#[derive(Deserialize)]
+pub struct GodboltPass {
+    pub name: String,
+    #[serde(rename = "irChanged", default)]
+    pub ir_changed: bool,
+    pub before: Vec<GodboltAsmBlock>,
+    pub after: Vec<GodboltAsmBlock>,
+}
+
+impl GodboltPass {
+    pub fn diff(&self) -> String {
+        let join = |lines: &[GodboltAsmBlock]| lines.iter().map(|l| l.text.as_str()).join("\n");
+        shared::diff_lines(&join(&self.before), &join(&self.after))
+    }
+}
+
+#[derive(Deserialize)]
+struct GodboltPipelineOutput {
+    results: HashMap<String, Vec<GodboltPass>>,
+}
+
+#[derive(Deserialize)]
+struct GodboltPipelineResponse {
+    #[serde(rename = "optPipelineOutput")]
+    opt_pipeline_output: Option<GodboltPipelineOutput>,
+}
+
+/// Compiles the input and returns the LLVM IR of the given function before and after every pass.
+pub async fn get_opt_pipeline<T: CompileTarget>(
+    client: &Client,
+    input: String,
+    flags: Option<String>,
+    function: &str,
+) -> anyhow::Result<Vec<GodboltPass>> {
+    let mut body = T::prepare_json_body(&input, flags.as_deref());
+    body["options"]["compilerOptions"] = serde_json::json!({
+        "produceOptPipeline": {
+            "filterDebugInfo": true,
+            "filterIRMetadata": true,
+            "fullModule": false,
+            "noDiscardValueNames": true,
+            "demangle": true,
+            "libraryFunctions": false
+        }
+    });
+
+    let response: GodboltPipelineResponse = client
+        .post(T::url())
+        .header("Accept", "application/json")
+        .json(&body)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let mut results = response
+        .opt_pipeline_output
+        .context("godbolt did not return an optimization pipeline")?
+        .results;
+
+    // Function names are demangled, so `square` should also find `example::square`.
+    // If that matches several functions, the shortest path wins, and ties are broken by name,
+    // so that the choice doesn't depend on the order of the map
+    let suffix = format!("::{function}");
+    let key = if results.contains_key(function) {
+        function.to_owned()
+    } else {
+        results
+            .keys()
+            .filter(|name| name.ends_with(&suffix))
+            .min_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)))
+            .cloned()
+            .with_context(|| {
+                format!(
+                    "function `{function}` not found, available functions: {}",
+                    results.keys().sorted().join(", ")
+                )
+            })?
+    };
+
+    Ok(results.remove(&key).unwrap_or_default())
+}
+
+#[derive(Deserialize)]
+pub struct InstructionInfo {
+    pub tooltip: String,
+    pub html: String,
+    pub url: String,
+}
+
+impl InstructionInfo {
+    /// Returns the instruction forms (mnemonic + operands) listed in the documentation, e.g. `VPSHUFB xmm1, xmm2, xmm3/m128`
+    pub fn operand_forms(&self, mnemonic: &str) -> Vec<String> {
+        let mnemonic = mnemonic.to_ascii_uppercase();
+        util::strip_html(&self.html.replace("<br>", "\n").replace("</td>", "\n"))
+            .lines()
+            .map(str::trim)
+            .filter(|line| {
+                line.strip_prefix(mnemonic.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+            })
+            .map(String::from)
+            .unique()
+            .take(10)
+            .collect()
+    }
+}
+
+/// Maps user-facing architecture names to godbolt's instruction set names
+pub fn instruction_set(arch: &str) -> Option<&'static str> {
+    match arch {
+        "x86" | "x86_64" | "x64" | "amd64" => Some("amd64"),
+        "arm" | "arm32" => Some("arm32"),
+        "aarch64" | "arm64" => Some("aarch64"),
+        _ => None,
+    }
+}
+
+/// The instruction set of the code that godbolt compiles with the given flags.
+/// A `--target` (or clang's `-target`) picks the architecture, otherwise the compilers produce x86-64
+pub fn flags_instruction_set(flags: Option<&str>) -> &'static str {
+    let mut words = flags.unwrap_or_default().split_ascii_whitespace();
+    let mut target = None;
+    while let Some(word) = words.next() {
+        if let Some(triple) = word.strip_prefix("--target=") {
+            target = Some(triple);
+        } else if word == "--target" || word == "-target" {
+            target = words.next();
+        }
+    }
+
+    target
+        .and_then(|triple| {
+            let arch = triple.split('-').next()?;
+            // e.g. `armv7-unknown-linux-gnueabihf` or `thumbv7em-none-eabi`
+            let arch = if arch.starts_with("arm") || arch.starts_with("thumb") {
+                "arm"
+            } else {
+                arch
+            };
+            instruction_set(arch)
+        })
+        .unwrap_or("amd64")
+}
+
+/// Looks up documentation for an assembly mnemonic
+pub async fn get_instruction_info(
+    client: &Client,
+    instruction_set: &str,
+    mnemonic: &str,
+) -> anyhow::Result<InstructionInfo> {
+    let response = client
+        .get(format!(
+            "https://godbolt.org/api/asm/{instruction_set}/{}",
+            mnemonic.to_ascii_lowercase()
+        ))
+        .header("Accept", "application/json")
+        .send()
+        .await?;
+
+    anyhow::ensure!(
+        response.status() != reqwest::StatusCode::NOT_FOUND,
+        "unknown instruction: {mnemonic}"
+    );
+
+    Ok(response.error_for_status()?.json().await?)
+}
+
+/// Collects the distinct instruction mnemonics used in an assembly listing
+pub fn mnemonics(asm: &str) -> Vec<&str> {
+    asm.lines()
+        // labels and directives are not indented
+        .filter(|line| line.starts_with(char::is_whitespace))
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|word| !word.starts_with('.') && !word.ends_with(':'))
+        .unique()
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct GodboltShortLink {
+    url: String,
+}
+
+/// Creates a godbolt short link that opens the compiler explorer with the same source and flags
+pub async fn create_short_link<T: CompileTarget>(
+    client: &Client,
+    input: &str,
+    flags: Option<&str>,
+) -> anyhow::Result<String> {
+    let response: GodboltShortLink = client
+        .post("https://godbolt.org/api/shortener")
+        .json(&T::prepare_shortlink_body(input, flags))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(response.url)
+}
+
+#[derive(Deserialize)]
+pub struct GodboltBuildResult {
+    pub code: i32,
+    #[serde(default)]
+    pub stderr: Vec<GodboltAsmBlock>,
+}
+
+#[derive(Deserialize)]
+pub struct GodboltExecResponse {
+    pub code: i32,
+    #[serde(rename = "didExecute", default)]
+    pub did_execute: bool,
+    #[serde(default)]
+    pub stdout: Vec<GodboltAsmBlock>,
+    #[serde(default)]
+    pub stderr: Vec<GodboltAsmBlock>,
+    #[serde(rename = "buildResult")]
+    pub build_result: Option<GodboltBuildResult>,
+}
+
+impl GodboltExecResponse {
+    pub fn output(&self) -> String {
+        let join = |lines: &[GodboltAsmBlock]| lines.iter().map(|l| l.text.as_str()).join("\n");
+
+        if !self.did_execute {
+            let stderr = self
+                .build_result
+                .as_ref()
+                .map(|build| join(&build.stderr))
+                .unwrap_or_default();
+            return util::strip_ansi(&stderr).into_owned();
+        }
+
+        let mut output = join(&self.stderr);
+        if !output.is_empty() {
+            output.push('\n');
+        }
+        output += &join(&self.stdout);
+        if self.code != 0 {
+            let _ = write!(output, "\nexit code: {}", self.code);
+        }
+        output
+    }
+}
+
+/// Compiles and executes the input, passing the given arguments and stdin to the program
+pub async fn execute<T: CompileTarget>(
+    client: &Client,
+    input: String,
+    flags: Option<String>,
+    args: Vec<String>,
+    stdin: String,
+) -> anyhow::Result<GodboltExecResponse> {
+    let mut body = T::prepare_json_body(&input, flags.as_deref());
+    body["options"]["compilerOptions"] = serde_json::json!({ "executorRequest": true });
+    body["options"]["filters"] = serde_json::json!({ "execute": true });
+    body["options"]["executeParameters"] = serde_json::json!({
+        "args": args,
+        "stdin": stdin,
+    });
+
+    client
+        .post(T::url())
+        .header("Accept", "application/json")
+        .json(&body)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+        .map_err(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_rustc_remark;
+    use super::RemarkKind;
+
+    #[test]
+    fn rustc_remarks() {
+        // from `rustc -Copt-level=3 -Cremark=all -Cdebuginfo=1`
+        let remark = parse_rustc_remark(
+            "note: /app/example.rs:10:30 inline (missed): '_ZN7example1f17hc1622337f820c637E' \
+             not inlined into '_ZN7example1g17habae4cd38371cc29E' because it should never be \
+             inlined (cost=never): noinline function attribute",
+        )
+        .unwrap();
+        assert_eq!(remark.kind, RemarkKind::Missed);
+        assert_eq!(remark.pass, "inline");
+        assert_eq!(remark.line, 10);
+        assert!(remark
+            .message
+            .starts_with("'_ZN7example1f17hc1622337f820c637E' not inlined"));
+        assert!(remark
+            .message
+            .ends_with("(cost=never): noinline function attribute"));
+
+        let remark = parse_rustc_remark(
+            "note: <source>:7:0 prologepilog (analysis): 40 stack bytes in function",
+        )
+        .unwrap();
+        assert_eq!(remark.kind, RemarkKind::Analysis);
+        assert_eq!(remark.pass, "prologepilog");
+        assert_eq!(remark.line, 7);
+        assert_eq!(remark.message, "40 stack bytes in function");
+
+        let remark =
+            parse_rustc_remark("note: /app/example.rs:3:5 loop-vectorize (failure):").unwrap();
+        assert_eq!(remark.kind, RemarkKind::Failure);
+        assert_eq!(remark.message, "");
+    }
+
+    #[test]
+    fn rustc_remarks_outside_of_the_source() {
+        assert!(parse_rustc_remark(
+            "note: /rustc/59807616e1fa2540724bfbac14d7976d7e4a3860/library/core/src/result.rs:2173:15 \
+             gvn (missed): load of type i64 not eliminated"
+        )
+        .is_none());
+        assert!(parse_rustc_remark("warning: unused variable: `x`").is_none());
+        assert!(parse_rustc_remark("note: required by a bound in `foo`").is_none());
+    }
+}
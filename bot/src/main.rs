@@ -10,7 +10,9 @@ use poise::PrefixFrameworkOptions;
 mod commands;
 mod godbolt;
 mod playground;
+mod pool;
 mod state;
+mod typescript;
 mod util;
 
 type State = state::State;
@@ -53,6 +55,15 @@ async fn main() -> anyhow::Result<()> {
                 commands::expand(),
                 commands::godbolt(),
                 commands::rustc(),
+                commands::remarks(),
+                commands::pipeline(),
+                commands::insn(),
+                commands::c(),
+                commands::cpp(),
+                commands::jsbench(),
+                commands::rhai(),
+                commands::jsdiff(),
+                commands::wasm(),
             ],
             ..Default::default()
         })
@@ -0,0 +1,329 @@
+//! A pool of worker processes that grows while jobs are waiting for a worker.
+//!
+//! A fixed number of workers is always running. When more jobs are in flight than that,
+//! extra workers are started (up to a maximum) so that one slow script doesn't hold up
+//! everyone else's. Extra workers that have been idle for a while are shut down again.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::Context;
+use ipc2_host::workerset::TimeoutAction;
+use ipc2_host::workerset::WorkerSet;
+use shared::ClientMessage;
+use shared::HostMessage;
+use shared::SessionId;
+use tokio::net::UnixListener;
+
+use crate::util;
+
+pub type Workers = WorkerSet<UnixListener, ClientMessage, HostMessage>;
+
+/// Extra workers that haven't been used for this long are shut down
+const EXTRA_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// How often idle extra workers are looked for
+const REAP_INTERVAL: Duration = Duration::from_secs(15);
+/// How much longer than the job's timeout ipc2 waits before it restarts the worker,
+/// see [`WorkerPool::send_timeout`]
+const TIMEOUT_MARGIN: Duration = Duration::from_millis(50);
+
+/// The error of [`WorkerPool::send_timeout`] when the worker was restarted because the job took
+/// too long. Any other error means that the worker exited without responding
+#[derive(Debug)]
+pub struct TimedOut(pub Duration);
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the worker did not respond within {:?}", self.0)
+    }
+}
+
+pub struct PoolConfig {
+    pub worker_path: String,
+    /// Number of workers that are always running
+    pub worker_count: usize,
+    /// Upper limit for the number of workers during bursts
+    pub max_worker_count: usize,
+    /// Number of workers that REPL sessions are spread over
+    pub session_worker_count: usize,
+    /// How long a job may take before its worker is restarted
+    pub timeout: Duration,
+}
+
+fn env_var<T: std::str::FromStr>(name: &str) -> anyhow::Result<Option<T>>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match env::var(name) {
+        Ok(value) => {
+            Ok(Some(value.parse().with_context(|| {
+                format!("invalid value for {name}: {value}")
+            })?))
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+impl PoolConfig {
+    /// Reads the configuration from `WORKER_PATH`, `WORKER_COUNT`, `WORKER_MAX_COUNT`,
+    /// `SESSION_WORKER_COUNT` and `WORKER_TIMEOUT_SECS`,
+    /// falling back to defaults for the ones that aren't set
+    pub fn from_env() -> anyhow::Result<Self> {
+        let worker_path =
+            env::var("WORKER_PATH").unwrap_or_else(|_| util::get_worker_path().into());
+        let worker_count = match env_var("WORKER_COUNT")? {
+            Some(count) => count,
+            None => std::thread::available_parallelism().map_or(1, |count| count.get()),
+        };
+        let max_worker_count = env_var("WORKER_MAX_COUNT")?.unwrap_or(worker_count * 2);
+        let session_worker_count = env_var("SESSION_WORKER_COUNT")?.unwrap_or(worker_count);
+        let timeout =
+            env_var("WORKER_TIMEOUT_SECS")?.map_or(Duration::from_secs(5), Duration::from_secs_f64);
+
+        anyhow::ensure!(worker_count > 0, "WORKER_COUNT must be at least 1");
+        anyhow::ensure!(
+            max_worker_count >= worker_count,
+            "WORKER_MAX_COUNT must be at least WORKER_COUNT"
+        );
+        anyhow::ensure!(
+            session_worker_count > 0,
+            "SESSION_WORKER_COUNT must be at least 1"
+        );
+
+        Ok(Self {
+            worker_path,
+            worker_count,
+            max_worker_count,
+            session_worker_count,
+            timeout,
+        })
+    }
+}
+
+/// Extra single-worker sets that are currently not running a job
+#[derive(Default)]
+struct Extras {
+    /// Idle workers, with when they were last used
+    idle: Vec<(Workers, Instant)>,
+    /// Number of extra workers, including the ones that are running a job right now
+    total: usize,
+}
+
+impl Extras {
+    /// Shuts down the idle workers that haven't been used for [`EXTRA_IDLE_TIMEOUT`].
+    /// Dropping a worker set closes its connection, and a worker exits once its connection is gone
+    fn reap(&mut self) {
+        let before = self.idle.len();
+        self.idle
+            .retain(|(_, last_used)| last_used.elapsed() < EXTRA_IDLE_TIMEOUT);
+        self.total -= before - self.idle.len();
+    }
+}
+
+/// Reaps the extra workers of the pool periodically until the pool is dropped,
+/// so that the workers of a burst don't keep running until the next job comes in
+async fn reap_extras(extras: Weak<Mutex<Extras>>) {
+    let mut interval = tokio::time::interval(REAP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let Some(extras) = extras.upgrade() else {
+            return;
+        };
+        extras.lock().unwrap().reap();
+    }
+}
+
+pub struct WorkerPool {
+    path: String,
+    base: Workers,
+    base_count: usize,
+    max_extra_count: usize,
+    timeout: Duration,
+    /// Shared with the task that reaps idle extra workers
+    extras: Arc<Mutex<Extras>>,
+    /// Jobs that were sent and haven't been answered yet
+    in_flight: AtomicUsize,
+}
+
+/// Decrements the in-flight counter and returns the extra worker to the pool when the job is done,
+/// even if the future sending it was dropped
+struct Checkout<'a> {
+    pool: &'a WorkerPool,
+    extra: Option<Workers>,
+    /// Whether a slot for an extra worker was reserved, even if starting it failed
+    reserved: bool,
+}
+
+impl Drop for Checkout<'_> {
+    fn drop(&mut self) {
+        self.pool.in_flight.fetch_sub(1, Ordering::Relaxed);
+
+        let mut extras = self.pool.extras.lock().unwrap();
+        match self.extra.take() {
+            Some(workers) => extras.idle.push((workers, Instant::now())),
+            None if self.reserved => extras.total -= 1,
+            None => {}
+        }
+    }
+}
+
+impl WorkerPool {
+    /// Starts `count` workers right away, and allows up to `max_count` during bursts
+    pub async fn new(
+        path: &str,
+        count: usize,
+        max_count: usize,
+        timeout: Duration,
+    ) -> anyhow::Result<Self> {
+        let base = WorkerSet::builder()
+            .worker_path(path)
+            .worker_count(count)
+            .finish()
+            .await?;
+        let extras = Arc::new(Mutex::new(Extras::default()));
+        tokio::spawn(reap_extras(Arc::downgrade(&extras)));
+
+        Ok(Self {
+            path: path.into(),
+            base,
+            base_count: count,
+            max_extra_count: max_count.saturating_sub(count),
+            timeout,
+            extras,
+            in_flight: AtomicUsize::new(0),
+        })
+    }
+
+    /// How long a job may take by default, see [`PoolConfig::timeout`]
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Sends a message to an idle worker, starting an extra one if all are busy.
+    /// Falls back to queueing on the always running workers once the maximum is reached.
+    /// If the worker didn't respond within `timeout`, the error is a [`TimedOut`].
+    pub async fn send_timeout(
+        &self,
+        message: HostMessage,
+        timeout: Duration,
+        action: TimeoutAction,
+    ) -> anyhow::Result<ClientMessage> {
+        let checkout = self.checkout().await;
+        let workers = checkout.extra.as_ref().unwrap_or(&self.base);
+
+        // ipc2 gets a little more time, so that its timeout can't be noticed before this one's
+        let send = workers.send_timeout(message, timeout + TIMEOUT_MARGIN, action);
+        tokio::pin!(send);
+        tokio::select! {
+            // both can be ready in the same poll, and then it was a timeout
+            biased;
+            () = tokio::time::sleep(timeout) => match send.await {
+                Ok(response) => Ok(response),
+                // still waiting for the response when the timeout passed,
+                // so the error is the worker being restarted, not the worker dying on its own
+                Err(err) => Err(anyhow::Error::from(err).context(TimedOut(timeout))),
+            },
+            response = &mut send => Ok(response?),
+        }
+    }
+
+    async fn checkout(&self) -> Checkout<'_> {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::Relaxed);
+        let mut checkout = Checkout {
+            pool: self,
+            extra: None,
+            reserved: false,
+        };
+        if in_flight < self.base_count {
+            return checkout;
+        }
+
+        let spawn = {
+            let mut extras = self.extras.lock().unwrap();
+            extras.reap();
+
+            if let Some((workers, _)) = extras.idle.pop() {
+                checkout.extra = Some(workers);
+                checkout.reserved = true;
+                return checkout;
+            }
+
+            // reserve the slot before starting the worker, so concurrent jobs don't overshoot
+            checkout.reserved = extras.total < self.max_extra_count;
+            if checkout.reserved {
+                extras.total += 1;
+            }
+            checkout.reserved
+        };
+
+        if spawn {
+            tracing::info!(in_flight, "All workers are busy, starting an extra one");
+            match WorkerSet::builder()
+                .worker_path(self.path.as_str())
+                .worker_count(1)
+                .finish()
+                .await
+            {
+                Ok(workers) => checkout.extra = Some(workers),
+                // the slot is given back when the job is done
+                Err(err) => tracing::error!(%err, "Failed to start an extra worker"),
+            }
+        }
+
+        checkout
+    }
+}
+
+/// Workers that own the REPL sessions.
+///
+/// A session lives inside of one worker process, so every session is pinned to one of them
+/// by its ID, and these workers never grow. When a worker is restarted after a timeout,
+/// all of its sessions are lost.
+pub struct SessionPool {
+    shards: Vec<WorkerPool>,
+    /// Sessions that were created so far, to notice when a worker lost one
+    known: Mutex<HashSet<SessionId>>,
+}
+
+impl SessionPool {
+    pub async fn new(path: &str, count: usize, timeout: Duration) -> anyhow::Result<Self> {
+        let mut shards = Vec::with_capacity(count);
+        for _ in 0..count {
+            shards.push(WorkerPool::new(path, 1, 1, timeout).await?);
+        }
+
+        Ok(Self {
+            shards,
+            known: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// The worker that owns the session
+    pub fn shard(&self, id: SessionId) -> &WorkerPool {
+        let mut hasher = DefaultHasher::new();
+        id.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
+
+    /// Records that the worker created the session.
+    /// Returns `true` if it existed before, i.e. the worker lost the previous one
+    pub fn created(&self, id: SessionId) -> bool {
+        !self.known.lock().unwrap().insert(id)
+    }
+
+    /// Forgets about a session whose loss the user already knows about
+    pub fn forget(&self, id: SessionId) {
+        self.known.lock().unwrap().remove(&id);
+    }
+}
@@ -1,19 +1,26 @@
+use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::sync::Mutex;
+use std::time::Duration;
 
 use anyhow::Context;
-use ipc2_host::workerset::WorkerSet;
+use ipc2_host::workerset::TimeoutAction;
 use itertools::Itertools;
+use poise::serenity_prelude::ChannelId;
 use reqwest::Client;
 use shared::ClientMessage;
 use shared::HostMessage;
+use shared::WorkerInfo;
 use sysinfo::CpuRefreshKind;
 use sysinfo::RefreshKind;
 use sysinfo::System;
 use sysinfo::SystemExt;
 use tokio::fs;
-use tokio::net::UnixListener;
 use tokio::sync::Mutex as AsyncMutex;
 
+use crate::pool::PoolConfig;
+use crate::pool::SessionPool;
+use crate::pool::WorkerPool;
 use crate::util;
 
 pub struct Docs {
@@ -50,9 +57,76 @@ impl Docs {
     }
 }
 
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Asks a worker for its protocol version and capabilities.
+/// Fails if the worker speaks a different protocol version than the bot,
+/// which is also the most likely reason for the handshake itself failing.
+async fn handshake(workers: &WorkerPool) -> Result<WorkerInfo, String> {
+    let response = workers
+        .send_timeout(
+            HostMessage::Handshake(shared::PROTOCOL_VERSION),
+            HANDSHAKE_TIMEOUT,
+            TimeoutAction::Restart,
+        )
+        .await;
+
+    match response {
+        Ok(ClientMessage::Handshake(info)) if info.protocol_version == shared::PROTOCOL_VERSION => {
+            Ok(info)
+        }
+        Ok(ClientMessage::Handshake(info)) => Err(format!(
+            "the worker speaks protocol version {}, but the bot expects version {}. Rebuild the worker.",
+            info.protocol_version,
+            shared::PROTOCOL_VERSION
+        )),
+        Ok(other) => Err(format!(
+            "the worker responded to the handshake with {other:?}, it is probably outdated. Rebuild the worker."
+        )),
+        Err(err) => Err(format!(
+            "the handshake with the worker failed ({err}), it probably speaks a different protocol version. Rebuild the worker."
+        )),
+    }
+}
+
+/// Caches whether channels are threads. Channels never change their type,
+/// so this saves a request to Discord on every `,js`
+#[derive(Default)]
+pub struct ThreadChannels {
+    is_thread: HashMap<ChannelId, bool>,
+    /// Cached channels, oldest first
+    order: VecDeque<ChannelId>,
+}
+
+impl ThreadChannels {
+    /// The oldest channel is evicted when more than this many are cached
+    const MAX_LEN: usize = 10_000;
+
+    pub fn get(&self, id: ChannelId) -> Option<bool> {
+        self.is_thread.get(&id).copied()
+    }
+
+    pub fn insert(&mut self, id: ChannelId, is_thread: bool) {
+        if self.is_thread.insert(id, is_thread).is_some() {
+            return;
+        }
+
+        self.order.push_back(id);
+        if self.order.len() > Self::MAX_LEN {
+            if let Some(oldest) = self.order.pop_front() {
+                self.is_thread.remove(&oldest);
+            }
+        }
+    }
+}
+
 pub struct State {
     pub rustc_lock: AsyncMutex<()>,
-    pub workers: WorkerSet<UnixListener, ClientMessage, HostMessage>,
+    pub workers: WorkerPool,
+    pub sessions: SessionPool,
+    pub thread_channels: Mutex<ThreadChannels>,
+    /// Result of the handshake with the workers. JS commands are disabled if it failed.
+    pub worker_info: Result<WorkerInfo, String>,
     pub reqwest: Client,
     pub system: Mutex<System>,
     pub docs: Docs,
@@ -60,13 +134,41 @@ pub struct State {
 
 impl State {
     pub async fn new() -> anyhow::Result<Self> {
-        let path = util::get_worker_path();
-        tracing::info!(%path, "Creating state");
+        let config = PoolConfig::from_env()?;
+        tracing::info!(
+            path = %config.worker_path,
+            count = config.worker_count,
+            max_count = config.max_worker_count,
+            session_count = config.session_worker_count,
+            timeout = ?config.timeout,
+            "Creating state"
+        );
         let docs = Docs::from_path("doc.bin").await?;
 
+        let workers = WorkerPool::new(
+            &config.worker_path,
+            config.worker_count,
+            config.max_worker_count,
+            config.timeout,
+        )
+        .await?;
+        let worker_info = handshake(&workers).await;
+        match &worker_info {
+            Ok(info) => tracing::info!(?info, "Worker handshake succeeded"),
+            Err(err) => tracing::error!(%err, "Worker handshake failed, disabling JS commands"),
+        }
+
         Ok(Self {
             rustc_lock: AsyncMutex::new(()),
-            workers: WorkerSet::builder().worker_path(path).finish().await?,
+            workers,
+            sessions: SessionPool::new(
+                &config.worker_path,
+                config.session_worker_count,
+                config.timeout,
+            )
+            .await?,
+            thread_channels: Mutex::default(),
+            worker_info,
             reqwest: Client::new(),
             system: Mutex::new(System::new_with_specifics(
                 RefreshKind::new()
@@ -0,0 +1,1144 @@
+//! Strips TypeScript type syntax so that the code can be run by a JavaScript engine.
+//!
+//! Types are replaced with spaces instead of being removed, so every byte of the output is at the
+//! same offset (and line and column) as in the input, and error positions reported by the engine
+//! apply to the original source as they are.
+//!
+//! This is not a TypeScript parser. It works on tokens and recognizes types by the context they
+//! appear in, which covers the syntax that shows up in snippets: annotations, interfaces, type
+//! aliases, `declare`, `as`/`satisfies` casts, non-null assertions, generics and class member
+//! modifiers. Syntax with runtime semantics, like enums and namespaces, is left alone, and so are
+//! types inside of template literal substitutions. The exception are constructor parameter
+//! properties, which look like types but assign fields, and are an [`Unsupported`] error instead.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use shared::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenKind {
+    Ident,
+    Number,
+    String,
+    Template,
+    Regex,
+    Punct,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Token {
+    kind: TokenKind,
+    start: usize,
+    end: usize,
+    newline_before: bool,
+}
+
+/// Punctuators that consist of more than one character, longest first.
+/// Nothing starts with `>`, so that closing angle brackets of generics are always separate tokens
+const PUNCTUATORS: &[&str] = &[
+    "...", "===", "!==", "**=", "<<=", "&&=", "||=", "??=", "=>", "==", "!=", "?.", "??", "&&",
+    "||", "**", "++", "--", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "<<", "<=",
+];
+
+/// Keywords after which an expression starts, as opposed to identifiers, which end one
+const EXPRESSION_KEYWORDS: &[&str] = &[
+    "return",
+    "typeof",
+    "instanceof",
+    "in",
+    "of",
+    "new",
+    "delete",
+    "void",
+    "throw",
+    "case",
+    "do",
+    "else",
+    "yield",
+    "await",
+    "extends",
+    "implements",
+];
+
+/// TypeScript syntax that does something at runtime, so it can't be replaced with spaces
+#[derive(Debug)]
+pub struct Unsupported {
+    pub message: &'static str,
+    pub span: Span,
+}
+
+const MODIFIERS: &[&str] = &[
+    "public",
+    "private",
+    "protected",
+    "readonly",
+    "declare",
+    "override",
+];
+
+struct Lexer<'a> {
+    code: &'a str,
+    pos: usize,
+    tokens: Vec<Token>,
+}
+
+impl Lexer<'_> {
+    fn peek(&self) -> Option<char> {
+        self.code[self.pos..].chars().next()
+    }
+
+    fn rest(&self) -> &str {
+        &self.code[self.pos..]
+    }
+
+    fn ends_expression(&self) -> bool {
+        self.tokens
+            .last()
+            .is_some_and(|token| ends_expression(token, &self.code[token.start..token.end]))
+    }
+
+    /// Skips whitespace and comments, returns whether there was a newline
+    fn skip_trivia(&mut self) -> bool {
+        let mut newline = false;
+        loop {
+            let rest = self.rest();
+            if let Some(c) = rest.chars().next().filter(|c| c.is_whitespace()) {
+                newline |= c == '\n';
+                self.pos += c.len_utf8();
+            } else if rest.starts_with("//") {
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+            } else if let Some(comment) = rest.strip_prefix("/*") {
+                let len = comment.find("*/").map_or(rest.len(), |end| end + 4);
+                newline |= rest[..len].contains('\n');
+                self.pos += len;
+            } else {
+                return newline;
+            }
+        }
+    }
+
+    fn eat_while(&mut self, f: impl Fn(char) -> bool) {
+        while let Some(c) = self.peek().filter(|&c| f(c)) {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn eat_string(&mut self, quote: char) {
+        self.pos += 1;
+        while let Some(c) = self.peek() {
+            self.pos += c.len_utf8();
+            match c {
+                '\\' => self.eat_escaped(),
+                '\n' => return,
+                c if c == quote => return,
+                _ => {}
+            }
+        }
+    }
+
+    fn eat_escaped(&mut self) {
+        if let Some(c) = self.peek() {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    /// Template literals are a single token, including their substitutions
+    fn eat_template(&mut self) {
+        self.pos += 1;
+        while let Some(c) = self.peek() {
+            self.pos += c.len_utf8();
+            match c {
+                '\\' => self.eat_escaped(),
+                '`' => return,
+                '$' if self.peek() == Some('{') => {
+                    self.pos += 1;
+                    // lex the substitution into a separate list and throw it away
+                    let outer = std::mem::take(&mut self.tokens);
+                    let mut depth = 1;
+                    while depth > 0 && self.next_token() {
+                        let token = self.tokens.last().unwrap();
+                        match &self.code[token.start..token.end] {
+                            "{" => depth += 1,
+                            "}" => depth -= 1,
+                            _ => {}
+                        }
+                    }
+                    self.tokens = outer;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn eat_regex(&mut self) {
+        self.pos += 1;
+        let mut in_class = false;
+        while let Some(c) = self.peek() {
+            if c == '\n' {
+                return;
+            }
+            self.pos += c.len_utf8();
+            match c {
+                '\\' => self.eat_escaped(),
+                '[' => in_class = true,
+                ']' => in_class = false,
+                '/' if !in_class => break,
+                _ => {}
+            }
+        }
+        self.eat_while(is_ident_char);
+    }
+
+    /// Lexes the next token, returns false at the end of the input
+    fn next_token(&mut self) -> bool {
+        let newline_before = self.skip_trivia();
+        let start = self.pos;
+        let Some(c) = self.peek() else {
+            return false;
+        };
+
+        let kind = match c {
+            '"' | '\'' => {
+                self.eat_string(c);
+                TokenKind::String
+            }
+            '`' => {
+                self.eat_template();
+                TokenKind::Template
+            }
+            '0'..='9' => {
+                self.eat_while(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+                TokenKind::Number
+            }
+            '.' if self.rest()[1..].starts_with(|c: char| c.is_ascii_digit()) => {
+                self.eat_while(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+                TokenKind::Number
+            }
+            '/' if !self.ends_expression() => {
+                self.eat_regex();
+                TokenKind::Regex
+            }
+            c if is_ident_char(c) || c == '#' => {
+                self.pos += c.len_utf8();
+                self.eat_while(is_ident_char);
+                TokenKind::Ident
+            }
+            _ => {
+                let rest = self.rest();
+                let len = PUNCTUATORS
+                    .iter()
+                    .find(|p| rest.starts_with(**p))
+                    // `a?.5:b` is a conditional, not optional chaining
+                    .filter(|p| **p != "?." || !rest[2..].starts_with(|c: char| c.is_ascii_digit()))
+                    .map_or(c.len_utf8(), |p| p.len());
+                self.pos += len;
+                TokenKind::Punct
+            }
+        };
+
+        self.tokens.push(Token {
+            kind,
+            start,
+            end: self.pos,
+            newline_before,
+        });
+        true
+    }
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$' || !c.is_ascii()
+}
+
+fn ends_expression(token: &Token, text: &str) -> bool {
+    match token.kind {
+        TokenKind::Ident => !EXPRESSION_KEYWORDS.contains(&text),
+        TokenKind::Punct => matches!(text, ")" | "]" | "}"),
+        _ => true,
+    }
+}
+
+#[derive(Debug)]
+enum Frame {
+    /// Parameter list of a function, `in_default` while in a default value
+    Params {
+        in_default: bool,
+    },
+    /// `in_initializer` while in the initializer of a field
+    ClassBody {
+        in_initializer: bool,
+    },
+    /// Braces of an import or export statement
+    ImportExport,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DeclState {
+    ExpectBinding,
+    /// In a destructuring pattern that ends at the token with this index
+    InPattern(usize),
+    AfterBinding,
+    Initializer,
+}
+
+/// A `let`, `const` or `var` declaration
+struct Decl {
+    depth: usize,
+    state: DeclState,
+}
+
+struct Stripper<'a> {
+    code: &'a str,
+    tokens: Vec<Token>,
+    /// Index of the matching closing bracket for every opening bracket
+    matching: Vec<Option<usize>>,
+    out: Vec<u8>,
+    frames: Vec<(Frame, usize)>,
+    decls: Vec<Decl>,
+    /// Indices of the closing parentheses of parameter lists, which can be followed by a return type
+    params_close: HashSet<usize>,
+    /// Between the `class` keyword and the class body
+    in_class_heading: bool,
+}
+
+impl<'a> Stripper<'a> {
+    fn new(code: &'a str) -> Self {
+        let mut lexer = Lexer {
+            code,
+            pos: 0,
+            tokens: Vec::new(),
+        };
+        while lexer.next_token() {}
+        let tokens = lexer.tokens;
+
+        let mut matching = vec![None; tokens.len()];
+        let mut open = Vec::new();
+        for (index, token) in tokens.iter().enumerate() {
+            if token.kind != TokenKind::Punct {
+                continue;
+            }
+            match &code[token.start..token.end] {
+                "(" | "[" | "{" => open.push(index),
+                ")" | "]" | "}" => {
+                    if let Some(open) = open.pop() {
+                        matching[open] = Some(index);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Self {
+            code,
+            tokens,
+            matching,
+            out: code.as_bytes().to_vec(),
+            frames: Vec::new(),
+            decls: Vec::new(),
+            params_close: HashSet::new(),
+            in_class_heading: false,
+        }
+    }
+
+    fn text(&self, index: usize) -> &'a str {
+        self.tokens
+            .get(index)
+            .map_or("", |token| &self.code[token.start..token.end])
+    }
+
+    fn is_punct(&self, index: usize, punct: &str) -> bool {
+        self.tokens
+            .get(index)
+            .is_some_and(|token| token.kind == TokenKind::Punct)
+            && self.text(index) == punct
+    }
+
+    fn is_ident(&self, index: usize) -> bool {
+        self.tokens
+            .get(index)
+            .is_some_and(|token| token.kind == TokenKind::Ident)
+    }
+
+    fn is_keyword(&self, index: usize, keyword: &str) -> bool {
+        self.is_ident(index) && self.text(index) == keyword
+    }
+
+    fn newline_before(&self, index: usize) -> bool {
+        self.tokens
+            .get(index)
+            .is_none_or(|token| token.newline_before)
+    }
+
+    /// Whether there is no whitespace between the token and the one before it
+    fn is_adjacent(&self, index: usize) -> bool {
+        index > 0 && self.tokens[index - 1].end == self.tokens[index].start
+    }
+
+    fn ends_expression(&self, index: usize) -> bool {
+        self.tokens
+            .get(index)
+            .is_some_and(|token| ends_expression(token, self.text(index)))
+    }
+
+    /// Index after the closing bracket matching the opening bracket at `index`
+    fn after_matching(&self, index: usize) -> usize {
+        self.matching
+            .get(index)
+            .copied()
+            .flatten()
+            .map_or(self.tokens.len(), |close| close + 1)
+    }
+
+    /// Replaces the tokens in `from..to` and everything between them with spaces
+    fn blank(&mut self, from: usize, to: usize) {
+        if from >= to {
+            return;
+        }
+        let start = self.tokens[from].start;
+        let end = self.tokens[to - 1].end;
+        for byte in &mut self.out[start..end] {
+            if *byte != b'\n' {
+                *byte = b' ';
+            }
+        }
+    }
+
+    /// Skips generic parameters or arguments starting at the `<` at `index`.
+    /// Returns the index after the closing `>`, or `None` if this doesn't look like generics
+    fn skip_angle(&self, mut index: usize) -> Option<usize> {
+        let mut depth = 0;
+        loop {
+            let token = self.tokens.get(index)?;
+            match (token.kind, self.text(index)) {
+                (TokenKind::Punct, "<") => depth += 1,
+                (TokenKind::Punct, ">") => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(index + 1);
+                    }
+                }
+                (TokenKind::Punct, "(" | "[" | "{") => {
+                    index = self.after_matching(index);
+                    continue;
+                }
+                (
+                    TokenKind::Punct,
+                    "," | "." | ":" | ";" | "|" | "&" | "=>" | "?" | "=" | "..." | "-",
+                ) => {}
+                (TokenKind::Punct | TokenKind::Regex, _) => return None,
+                _ => {}
+            }
+            index += 1;
+        }
+    }
+
+    /// Skips the type starting at `index`, returns the index after it
+    fn skip_type(&self, mut index: usize) -> usize {
+        if self.is_punct(index, "|") || self.is_punct(index, "&") {
+            index += 1;
+        }
+
+        loop {
+            index = self.skip_primary_type(index);
+
+            if self.is_punct(index, "|") || self.is_punct(index, "&") {
+                index += 1;
+            } else if self.is_keyword(index, "is") {
+                // type predicate: `x is T`
+                index = self.skip_type(index + 1);
+                return index;
+            } else if self.is_keyword(index, "extends") && !self.newline_before(index) {
+                // conditional type: `T extends U ? X : Y`
+                index = self.skip_type(index + 1);
+                if self.is_punct(index, "?") {
+                    index = self.skip_type(index + 1);
+                    if self.is_punct(index, ":") {
+                        index = self.skip_type(index + 1);
+                    }
+                }
+                return index;
+            } else {
+                return index;
+            }
+        }
+    }
+
+    fn skip_primary_type(&self, mut index: usize) -> usize {
+        while matches!(
+            self.text(index),
+            "keyof" | "typeof" | "readonly" | "unique" | "infer" | "asserts"
+        ) && self.is_ident(index + 1)
+        {
+            index += 1;
+        }
+
+        let Some(token) = self.tokens.get(index) else {
+            return index;
+        };
+        match (token.kind, self.text(index)) {
+            (TokenKind::Punct, "(") => {
+                index = self.after_matching(index);
+                // function type
+                if self.is_punct(index, "=>") {
+                    return self.skip_type(index + 1);
+                }
+            }
+            (TokenKind::Punct, "<") => {
+                // generic function type
+                let Some(after) = self.skip_angle(index) else {
+                    return index;
+                };
+                return self.skip_primary_type(after);
+            }
+            (TokenKind::Punct, "{" | "[") => index = self.after_matching(index),
+            (TokenKind::Ident, "new") => return self.skip_primary_type(index + 1),
+            (TokenKind::Ident, _) => {
+                index += 1;
+                while self.is_punct(index, ".") && self.is_ident(index + 1) {
+                    index += 2;
+                }
+                if self.is_punct(index, "<") {
+                    index = self.skip_angle(index).unwrap_or(index);
+                }
+            }
+            (TokenKind::String | TokenKind::Number | TokenKind::Template, _) => index += 1,
+            (TokenKind::Punct, "-")
+                if self.tokens.get(index + 1).map(|t| t.kind) == Some(TokenKind::Number) =>
+            {
+                index += 2
+            }
+            _ => return index,
+        }
+
+        // array types
+        while self.is_punct(index, "[") && !self.newline_before(index) {
+            index = self.after_matching(index);
+        }
+        index
+    }
+
+    /// Finds the end of a statement that is only made of types, like `declare const x: number;`
+    fn statement_end(&self, mut index: usize) -> usize {
+        let start = index;
+        while let Some(token) = self.tokens.get(index) {
+            let text = self.text(index);
+            if index > start + 1 && token.newline_before {
+                let previous = self.text(index - 1);
+                let continues = matches!(previous, "," | ":" | "|" | "&" | "=" | "=>" | "extends")
+                    || matches!(text, "|" | "&" | "." | "=" | "=>" | "extends" | "{");
+                if !continues {
+                    return index;
+                }
+            }
+
+            match (token.kind, text) {
+                (TokenKind::Punct, ";") => return index + 1,
+                (TokenKind::Punct, "(" | "[" | "{") => index = self.after_matching(index),
+                _ => index += 1,
+            }
+        }
+        index
+    }
+
+    /// Index of the next `{` at the same bracket depth
+    fn next_brace(&self, mut index: usize) -> usize {
+        while index < self.tokens.len() && !self.is_punct(index, "{") {
+            if self.is_punct(index, "(") || self.is_punct(index, "[") {
+                index = self.after_matching(index);
+            } else {
+                index += 1;
+            }
+        }
+        index
+    }
+
+    fn at_statement_start(&self, index: usize) -> bool {
+        index == 0
+            || self.newline_before(index)
+            || matches!(self.text(index - 1), ";" | "{" | "}" | "export" | "declare")
+    }
+
+    /// Handles statements that only exist for the type checker.
+    /// Returns the index after the statement if there was one
+    fn type_only_statement(&self, index: usize) -> Option<usize> {
+        let keyword = self.text(index);
+        let next = self.text(index + 1);
+
+        match keyword {
+            "export" if self.is_keyword(index + 1, "type") && self.is_punct(index + 2, "{") => {
+                Some(self.statement_end(index))
+            }
+            "export" | "declare" => self.type_only_statement(index + 1),
+            "import" if next == "type" && !matches!(self.text(index + 2), "from" | "," | "=") => {
+                Some(self.statement_end(index))
+            }
+            "interface" if self.is_ident(index + 1) => {
+                let brace = self.next_brace(index);
+                (brace < self.tokens.len()).then(|| self.after_matching(brace))
+            }
+            "type" if self.is_ident(index + 1) => {
+                let mut after_name = index + 2;
+                if self.is_punct(after_name, "<") {
+                    after_name = self.skip_angle(after_name)?;
+                }
+                if !self.is_punct(after_name, "=") {
+                    return None;
+                }
+                let mut end = self.skip_type(after_name + 1);
+                if self.is_punct(end, ";") {
+                    end += 1;
+                }
+                Some(end)
+            }
+            _ if self.is_keyword(index.wrapping_sub(1), "declare") => {
+                Some(self.statement_end(index))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the `(` at `index` starts a parameter list
+    fn is_params(&self, index: usize) -> bool {
+        if index > 0
+            && matches!(
+                self.text(index - 1),
+                "if" | "while" | "for" | "switch" | "with"
+            )
+        {
+            return false;
+        }
+
+        let after = self.after_matching(index);
+        if self.is_punct(after, "=>") || self.is_punct(after, "{") {
+            return true;
+        }
+
+        // return type
+        if self.is_punct(after, ":") {
+            let after_type = self.skip_type(after + 1);
+            return self.is_punct(after_type, "{") || self.is_punct(after_type, "=>");
+        }
+
+        false
+    }
+
+    /// Whether the `:` at `index` starts a type annotation
+    fn is_annotation(&self, index: usize) -> bool {
+        let previous = index.checked_sub(1);
+        let previous_text = previous.map_or("", |previous| self.text(previous));
+
+        if previous.is_some_and(|previous| self.params_close.contains(&previous)) {
+            return true;
+        }
+
+        if let Some(decl) = self.decls.last() {
+            if decl.depth == self.frames.len() && decl.state == DeclState::AfterBinding {
+                return true;
+            }
+        }
+
+        let previous_is_name = previous.is_some_and(|previous| self.is_ident(previous));
+        match self.frames.last() {
+            Some((Frame::Params { in_default: false }, _)) => {
+                previous_is_name || matches!(previous_text, "?" | "}" | "]")
+            }
+            Some((
+                Frame::ClassBody {
+                    in_initializer: false,
+                },
+                _,
+            )) => {
+                previous_is_name
+                    || matches!(previous_text, "?" | "!" | "]")
+                    || previous.is_some_and(|previous| {
+                        matches!(
+                            self.tokens[previous].kind,
+                            TokenKind::String | TokenKind::Number
+                        )
+                    })
+            }
+            _ => false,
+        }
+    }
+
+    fn in_class_body(&self) -> bool {
+        matches!(
+            self.frames.last(),
+            Some((
+                Frame::ClassBody {
+                    in_initializer: false
+                },
+                _
+            ))
+        )
+    }
+
+    fn in_params(&self) -> bool {
+        matches!(
+            self.frames.last(),
+            Some((Frame::Params { in_default: false }, _))
+        )
+    }
+
+    /// Whether the token at `index` is a modifier of a constructor parameter, which makes the
+    /// parameter a field that is assigned the argument
+    fn is_parameter_property(&self, index: usize) -> bool {
+        MODIFIERS.contains(&self.text(index))
+            && self.is_ident(index)
+            && self.in_params()
+            && !self.newline_before(index + 1)
+            && self.is_ident(index + 1)
+    }
+
+    fn strip(mut self) -> Result<String, Unsupported> {
+        let mut index = 0;
+        while index < self.tokens.len() {
+            if self.is_parameter_property(index) {
+                let token = self.tokens[index];
+                return Err(Unsupported {
+                    message: "parameter properties are not supported, \
+                              declare the field and assign it in the constructor instead",
+                    span: Span {
+                        lo: token.start as u32,
+                        hi: token.end as u32,
+                    },
+                });
+            }
+
+            if let Some(next) = self.strip_at(index) {
+                self.blank(index, next);
+                index = next;
+                continue;
+            }
+
+            self.track(index);
+            index += 1;
+        }
+
+        // only whole characters were replaced, and only with ASCII spaces
+        Ok(String::from_utf8(self.out).unwrap())
+    }
+
+    /// Returns the index after the type syntax that starts at `index`, if any
+    fn strip_at(&self, index: usize) -> Option<usize> {
+        let token = self.tokens[index];
+        let text = self.text(index);
+
+        match token.kind {
+            TokenKind::Ident => {
+                if self.at_statement_start(index) {
+                    if let Some(end) = self.type_only_statement(index) {
+                        return Some(end);
+                    }
+                }
+
+                match text {
+                    "abstract" if self.is_keyword(index + 1, "class") => Some(index + 1),
+                    // abstract members have no body
+                    "abstract" if self.in_class_body() => Some(self.statement_end(index)),
+                    "implements" if self.in_class_heading => Some(self.next_brace(index)),
+                    "as" | "satisfies"
+                        if index > 0
+                            && self.ends_expression(index - 1)
+                            && !matches!(self.frames.last(), Some((Frame::ImportExport, _))) =>
+                    {
+                        if text == "as" && self.is_keyword(index + 1, "const") {
+                            Some(index + 2)
+                        } else {
+                            Some(self.skip_type(index + 1))
+                        }
+                    }
+                    // `this` parameter, with the comma after it if there are more parameters
+                    "this"
+                        if self.in_params()
+                            && self.is_punct(index.wrapping_sub(1), "(")
+                            && self.is_punct(index + 1, ":") =>
+                    {
+                        let end = self.skip_type(index + 2);
+                        Some(end + usize::from(self.is_punct(end, ",")))
+                    }
+                    _ if MODIFIERS.contains(&text)
+                        && self.in_class_body()
+                        && !self.newline_before(index + 1)
+                        && (self.is_ident(index + 1)
+                            || self.is_punct(index + 1, "[")
+                            || self.is_punct(index + 1, "*")) =>
+                    {
+                        Some(index + 1)
+                    }
+                    _ => None,
+                }
+            }
+            TokenKind::Punct => match text {
+                ":" if self.is_annotation(index) => Some(self.skip_type(index + 1)),
+                // optional parameters and fields
+                "?" if (self.in_params() || self.in_class_body())
+                    && index > 0
+                    && self.is_ident(index - 1)
+                    && matches!(self.text(index + 1), ":" | "," | ")" | ";" | "=" | "(") =>
+                {
+                    Some(index + 1)
+                }
+                // non-null assertion
+                "!" if self.is_adjacent(index)
+                    && self.ends_expression(index - 1)
+                    && (index + 1 == self.tokens.len()
+                        || matches!(
+                            self.text(index + 1),
+                            "." | "?." | ")" | "]" | ";" | "," | "[" | "(" | "=" | ":"
+                        )) =>
+                {
+                    Some(index + 1)
+                }
+                // index signature
+                "[" if self.in_class_body()
+                    && self.is_ident(index + 1)
+                    && self.is_punct(index + 2, ":") =>
+                {
+                    Some(self.statement_end(index))
+                }
+                "<" => {
+                    let end = self.skip_angle(index)?;
+                    let generic_call = self.is_adjacent(index)
+                        && self.is_ident(index - 1)
+                        && !EXPRESSION_KEYWORDS.contains(&self.text(index - 1))
+                        && (self.is_punct(end, "(")
+                            || self.is_punct(end, "{")
+                            || matches!(
+                                self.tokens.get(end).map(|t| t.kind),
+                                Some(TokenKind::Template)
+                            )
+                            || self.is_keyword(end, "extends")
+                            || self.is_keyword(end, "implements"));
+                    // `<T>(x: T) => x` and `async <T>(x: T) => x`
+                    let generic_arrow = (index == 0
+                        || !self.ends_expression(index - 1)
+                        || self.is_keyword(index - 1, "async"))
+                        && self.is_punct(end, "(")
+                        && self.is_params(end);
+                    (generic_call || generic_arrow).then_some(end)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Keeps track of brackets, declarations and class bodies for a token that was not stripped
+    fn track(&mut self, index: usize) {
+        let token = self.tokens[index];
+        let text = self.text(index);
+        let depth = self.frames.len();
+
+        if let Some((Frame::ClassBody { in_initializer }, _)) = self.frames.last_mut() {
+            if (token.newline_before && token.kind == TokenKind::Ident) || text == ";" {
+                *in_initializer = false;
+            }
+        }
+
+        // declarations
+        let decl_state = self
+            .decls
+            .last()
+            .filter(|decl| decl.depth == depth)
+            .map(|decl| decl.state);
+        if let Some(state) = decl_state {
+            let state = match (state, token.kind, text) {
+                (_, TokenKind::Punct, ";") => None,
+                (DeclState::ExpectBinding, TokenKind::Ident, _) => Some(DeclState::AfterBinding),
+                (DeclState::ExpectBinding, TokenKind::Punct, "{" | "[") => Some(
+                    DeclState::InPattern(self.matching[index].unwrap_or(usize::MAX)),
+                ),
+                (DeclState::AfterBinding, TokenKind::Punct, ",") => Some(DeclState::ExpectBinding),
+                (DeclState::AfterBinding, _, _) => Some(DeclState::Initializer),
+                (DeclState::Initializer, TokenKind::Punct, ",") => Some(DeclState::ExpectBinding),
+                (state, _, _) => Some(state),
+            };
+
+            match state {
+                Some(state) => self.decls.last_mut().unwrap().state = state,
+                None => {
+                    self.decls.pop();
+                }
+            }
+        }
+
+        if token.kind == TokenKind::Ident {
+            match text {
+                "let" | "const" | "var" if !self.is_keyword(index + 1, "enum") => {
+                    self.decls.push(Decl {
+                        depth,
+                        state: DeclState::ExpectBinding,
+                    })
+                }
+                "class" => self.in_class_heading = true,
+                _ => {}
+            }
+        }
+
+        self.track_brackets(index);
+    }
+
+    fn track_brackets(&mut self, index: usize) {
+        let token = self.tokens[index];
+        if token.kind != TokenKind::Punct {
+            return;
+        }
+
+        let text = self.text(index);
+        match self.frames.last_mut() {
+            Some((Frame::Params { in_default }, _)) => match text {
+                "=" => *in_default = true,
+                "," => *in_default = false,
+                _ => {}
+            },
+            Some((Frame::ClassBody { in_initializer }, _)) if text == "=" => *in_initializer = true,
+            _ => {}
+        }
+
+        match text {
+            "(" | "[" | "{" => {
+                let frame = match text {
+                    "(" if self.is_params(index) => {
+                        if let Some(close) = self.matching[index] {
+                            self.params_close.insert(close);
+                        }
+                        Frame::Params { in_default: false }
+                    }
+                    "{" if self.in_class_heading => {
+                        self.in_class_heading = false;
+                        Frame::ClassBody {
+                            in_initializer: false,
+                        }
+                    }
+                    "{" if index > 0 && matches!(self.text(index - 1), "import" | "export") => {
+                        Frame::ImportExport
+                    }
+                    _ => Frame::Other,
+                };
+                self.frames
+                    .push((frame, self.matching[index].unwrap_or(usize::MAX)));
+            }
+            ")" | "]" | "}" => {
+                if self.frames.last().is_some_and(|(_, close)| *close == index) {
+                    self.frames.pop();
+                }
+
+                let depth = self.frames.len();
+                self.decls.retain(|decl| decl.depth <= depth);
+                if let Some(decl) = self.decls.last_mut() {
+                    if decl.state == DeclState::InPattern(index) {
+                        decl.state = DeclState::AfterBinding;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Replaces all TypeScript type syntax in the code with spaces
+pub fn strip_types(code: &str) -> Result<String, Unsupported> {
+    Stripper::new(code).strip()
+}
+
+/// Shows the lines of the code that the spans point to, with the spanned part underlined
+pub fn excerpt(code: &str, spans: &[Span]) -> String {
+    let mut output = String::new();
+
+    for span in spans {
+        let lo = (span.lo as usize).min(code.len());
+        let hi = (span.hi as usize).clamp(lo, code.len());
+        if !code.is_char_boundary(lo) || !code.is_char_boundary(hi) {
+            continue;
+        }
+
+        let line_start = code[..lo].rfind('\n').map_or(0, |newline| newline + 1);
+        let line_end = code[lo..]
+            .find('\n')
+            .map_or(code.len(), |newline| lo + newline);
+        let line_number = code[..lo].matches('\n').count() + 1;
+        let column = code[line_start..lo].chars().count();
+        let width = code[lo..hi.min(line_end)].chars().count().max(1);
+
+        let _ = writeln!(output, "{line_number}:{}", column + 1);
+        let _ = writeln!(output, "{}", &code[line_start..line_end]);
+        let _ = writeln!(output, "{}{}", " ".repeat(column), "^".repeat(width));
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::strip_types;
+    use super::Unsupported;
+
+    /// Strips the types of `ts` and checks that the result is `js`, ignoring whitespace,
+    /// and that every byte stayed in place
+    #[track_caller]
+    fn check(ts: &str, js: &str) {
+        let stripped = strip_types(ts).unwrap();
+        assert_eq!(stripped.len(), ts.len(), "length changed: {stripped:?}");
+        assert!(
+            stripped
+                .char_indices()
+                .all(|(index, c)| c == ' ' || ts[index..].starts_with(c)),
+            "bytes moved: {stripped:?}"
+        );
+
+        let without_whitespace = |s: &str| s.split_whitespace().collect::<Vec<_>>().join(" ");
+        assert_eq!(without_whitespace(&stripped), without_whitespace(js));
+    }
+
+    #[test]
+    fn annotations() {
+        check(
+            "function add(a: number, b?: number): number { return a + (b ?? 0); }",
+            "function add(a , b  ) { return a + (b ?? 0); }",
+        );
+        check(
+            "let x: Array<string> = [], y: { a: number } = { a: 1 };",
+            "let x = [], y = { a: 1 };",
+        );
+    }
+
+    #[test]
+    fn generics() {
+        check(
+            "function id<T>(x: T): T { return x; }\nid<number>(1);",
+            "function id (x ) { return x; }\nid (1);",
+        );
+        check(
+            "const m = new Map<string, Array<number>>();",
+            "const m = new Map ();",
+        );
+    }
+
+    #[test]
+    fn comparisons_are_kept() {
+        check(
+            "if (a < b && c > d) f(a < b, c > d);",
+            "if (a < b && c > d) f(a < b, c > d);",
+        );
+        check(
+            "const big = n >> 2 > m >>> 1;",
+            "const big = n >> 2 > m >>> 1;",
+        );
+    }
+
+    #[test]
+    fn object_types_next_to_comparisons() {
+        check(
+            "let o: { a: number } = { a: 1 }; if (o.a < 2 && o.a > 0) {}",
+            "let o = { a: 1 }; if (o.a < 2 && o.a > 0) {}",
+        );
+        check(
+            "function f(p: { x: number; y: number }) { return p.x < p.y; }",
+            "function f(p ) { return p.x < p.y; }",
+        );
+    }
+
+    #[test]
+    fn casts() {
+        check(
+            "const n = (x as any).length as number;",
+            "const n = (x ).length ;",
+        );
+        check(
+            "const c = { port: 80 } satisfies Config;",
+            "const c = { port: 80 } ;",
+        );
+        check("const v = value!.field!;", "const v = value .field ;");
+    }
+
+    #[test]
+    fn arrow_functions() {
+        check(
+            "const f = (a: number, b: string): boolean => a > b.length;",
+            "const f = (a , b ) => a > b.length;",
+        );
+        check("const id = <T,>(x: T): T => x;", "const id = (x ) => x;");
+        check(
+            "const g = async <T,>(x: T): Promise<T[]> => [x];",
+            "const g = async (x ) => [x];",
+        );
+        check(
+            "const h = (): { a: number } => ({ a: 1 });",
+            "const h = () => ({ a: 1 });",
+        );
+    }
+
+    #[test]
+    fn declarations() {
+        check(
+            "interface Point { x: number; y: number }\ntype P = Point | null;\nlet p = 1;",
+            "let p = 1;",
+        );
+        check("declare const config: any;\nconfig;", "config;");
+    }
+
+    #[test]
+    fn class_members() {
+        check(
+            "class A implements B { private readonly x: number = 1; constructor(y: string) {} }",
+            "class A { x = 1; constructor(y ) {} }",
+        );
+        check(
+            "class A { readonly [key: string]: number; protected override m(): void {} }",
+            "class A { m() {} }",
+        );
+    }
+
+    #[test]
+    fn parameter_properties() {
+        let code = "class A { constructor(x: number, private readonly y: string) {} }";
+        let Err(Unsupported { span, .. }) = strip_types(code) else {
+            panic!("parameter property was stripped");
+        };
+        assert_eq!(&code[span.lo as usize..span.hi as usize], "private");
+
+        // only in parameter lists, and only in front of the parameter's name
+        check(
+            "function f(readonly: boolean) {}",
+            "function f(readonly ) {}",
+        );
+        check(
+            "const public_ = { public: 1 };",
+            "const public_ = { public: 1 };",
+        );
+    }
+
+    #[test]
+    fn this_parameter() {
+        check(
+            "function f(this: Window, a: number) { return this; }",
+            "function f( a ) { return this; }",
+        );
+        check(
+            "function f(this: { n: number }) { return this.n; }",
+            "function f( ) { return this.n; }",
+        );
+        check("f(this, a);", "f(this, a);");
+    }
+
+    #[test]
+    fn unterminated() {
+        check("interface A", "interface A");
+        check("interface a;", "interface a;");
+        check("class A implements B", "class A");
+        for code in [
+            "type",
+            "type A =",
+            "let x:",
+            "f<",
+            "function f(",
+            "(a: ",
+            "a as",
+        ] {
+            strip_types(code).unwrap();
+        }
+    }
+}
@@ -11,7 +11,14 @@ use std::error::Error;
 use sublime_fuzzy::best_match;
 
 pub fn shrink_to_fit(input: &str) -> &str {
-    let len = 1980.min(input.len());
+    shrink_to(input, 1980)
+}
+
+pub fn shrink_to(input: &str, max: usize) -> &str {
+    let mut len = max.min(input.len());
+    while !input.is_char_boundary(len) {
+        len -= 1;
+    }
     &input[..len]
 }
 
@@ -29,6 +36,16 @@ pub fn strip_ansi(input: &str) -> Cow<'_, str> {
     ANSI_REGEX.replace_all(input, "")
 }
 
+pub fn strip_html(input: &str) -> String {
+    static TAG_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new("<[^>]*>").unwrap());
+    TAG_REGEX
+        .replace_all(input, "")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
 pub fn strip_header_stderr(input: &str) -> &str {
     let input = input.trim_start_matches("   Compiling playground v0.0.1 (/playground)");
     let input = input.trim_start();
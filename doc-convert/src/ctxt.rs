@@ -1,4 +1,5 @@
 use itertools::Itertools;
+use rustdoc_types::Constant;
 use rustdoc_types::Crate;
 use rustdoc_types::Enum;
 use rustdoc_types::FnDecl;
@@ -13,11 +14,18 @@ use rustdoc_types::Impl;
 use rustdoc_types::Import;
 use rustdoc_types::Item;
 use rustdoc_types::ItemEnum;
+use rustdoc_types::MacroKind;
 use rustdoc_types::Module;
 use rustdoc_types::Path;
+use rustdoc_types::Primitive;
+use rustdoc_types::ProcMacro;
+use rustdoc_types::Static;
 use rustdoc_types::Struct;
 use rustdoc_types::StructKind;
+use rustdoc_types::Trait;
+use rustdoc_types::TraitBoundModifier;
 use rustdoc_types::Type;
+use rustdoc_types::Typedef;
 use rustdoc_types::Union;
 use rustdoc_types::Variant;
 use rustdoc_types::VariantKind;
@@ -97,6 +105,38 @@ impl CrateCtxt {
         expect_item_kind!(self, id, ItemEnum::Variant(v) => v)
     }
 
+    pub fn expect_trait(&self, id: &Id) -> (&Item, &Trait, &str) {
+        expect_item_kind!(self, id, ItemEnum::Trait(t) => t)
+    }
+
+    pub fn expect_typedef(&self, id: &Id) -> (&Item, &Typedef, &str) {
+        expect_item_kind!(self, id, ItemEnum::Typedef(t) => t)
+    }
+
+    pub fn expect_constant(&self, id: &Id) -> (&Item, &Constant, &str) {
+        expect_item_kind!(self, id, ItemEnum::Constant(c) => c)
+    }
+
+    pub fn expect_static(&self, id: &Id) -> (&Item, &Static, &str) {
+        expect_item_kind!(self, id, ItemEnum::Static(s) => s)
+    }
+
+    pub fn expect_assoc_const(&self, id: &Id) -> (&Item, (&Type, &Option<String>), &str) {
+        expect_item_kind!(self, id, ItemEnum::AssocConst { type_, default } => (type_, default))
+    }
+
+    pub fn expect_macro(&self, id: &Id) -> (&Item, &String, &str) {
+        expect_item_kind!(self, id, ItemEnum::Macro(m) => m)
+    }
+
+    pub fn expect_proc_macro(&self, id: &Id) -> (&Item, &ProcMacro, &str) {
+        expect_item_kind!(self, id, ItemEnum::ProcMacro(m) => m)
+    }
+
+    pub fn expect_primitive(&self, id: &Id) -> (&Item, &Primitive) {
+        expect_item_kind_unnamed!(self, id, ItemEnum::Primitive(p) => p)
+    }
+
     pub fn run_visitor(&self, out: &mut Output) {
         let root = &self.krate.root;
         self.visit_module(root, out);
@@ -130,13 +170,13 @@ impl CrateCtxt {
             ItemEnum::Impl(_) => todo!(),                               // ???
             ItemEnum::Typedef(_) => self.visit_typedef(id, out),
             ItemEnum::OpaqueTy(_) => {} // ???
-            ItemEnum::Constant(_) => {}
-            ItemEnum::Static(_) => {} // maybe?
+            ItemEnum::Constant(_) => self.visit_constant(id, out),
+            ItemEnum::Static(_) => self.visit_static(id, out),
             ItemEnum::ForeignType => {}
             ItemEnum::Macro(_) => self.visit_macro(id, out),
             ItemEnum::ProcMacro(_) => self.visit_proc_macro(id, out),
-            ItemEnum::Primitive(_) => {} // can this even appear here?
-            ItemEnum::AssocConst { .. } => {}
+            ItemEnum::Primitive(_) => self.visit_primitive(id, out),
+            ItemEnum::AssocConst { .. } => self.visit_assoc_const(id, out),
             ItemEnum::AssocType { .. } => {}
         }
     }
@@ -181,11 +221,106 @@ impl CrateCtxt {
         out.index.push(path.into());
         out.docs.push(doc_string.into());
     }
-    fn visit_trait(&self, id: &Id, out: &mut Output) {}
+    fn visit_trait(&self, id: &Id, out: &mut Output) {
+        let (item, trait_, name) = self.expect_trait(id);
+        let doc_string = Self::document_item(item, |out| {
+            self.write_trait(out, trait_, name);
+        });
+        let path = out.to_path_string_with(name);
+        out.index.push(path.into());
+        out.docs.push(doc_string.into());
+
+        // methods are indexed as `Trait::method`
+        out.segment_stack.push(name.to_owned());
+        for item in &trait_.items {
+            self.visit_item(item, out);
+        }
+        out.segment_stack.pop();
+    }
     fn visit_trait_alias(&self, id: &Id, out: &mut Output) {}
-    fn visit_typedef(&self, id: &Id, out: &mut Output) {}
-    fn visit_macro(&self, id: &Id, out: &mut Output) {}
-    fn visit_proc_macro(&self, id: &Id, out: &mut Output) {}
+    fn visit_typedef(&self, id: &Id, out: &mut Output) {
+        let (item, typedef, name) = self.expect_typedef(id);
+        let doc_string = Self::document_item(item, |out| {
+            self.write_typedef(out, typedef, name);
+        });
+        let path = out.to_path_string_with(name);
+        out.index.push(path.into());
+        out.docs.push(doc_string.into());
+    }
+    fn visit_constant(&self, id: &Id, out: &mut Output) {
+        let (item, constant, name) = self.expect_constant(id);
+        let doc_string = Self::document_item(item, |out| {
+            self.write_constant(out, constant, name);
+        });
+        let path = out.to_path_string_with(name);
+        out.index.push(path.into());
+        out.docs.push(doc_string.into());
+    }
+    fn visit_static(&self, id: &Id, out: &mut Output) {
+        let (item, statik, name) = self.expect_static(id);
+        let doc_string = Self::document_item(item, |out| {
+            self.write_static(out, statik, name);
+        });
+        let path = out.to_path_string_with(name);
+        out.index.push(path.into());
+        out.docs.push(doc_string.into());
+    }
+    fn visit_assoc_const(&self, id: &Id, out: &mut Output) {
+        let (item, (type_, default), name) = self.expect_assoc_const(id);
+        let doc_string = Self::document_item(item, |out| {
+            out.push_str("const ");
+            out.push_str(name);
+            out.push_str(": ");
+            self.write_type(out, type_);
+            if let Some(default) = default {
+                out.push_str(" = ");
+                out.push_str(default);
+            }
+            out.push(';');
+        });
+        let path = out.to_path_string_with(name);
+        out.index.push(path.into());
+        out.docs.push(doc_string.into());
+    }
+    fn visit_macro(&self, id: &Id, out: &mut Output) {
+        let (item, source, name) = self.expect_macro(id);
+        // rustdoc already replaces the bodies of the arms with `{ ... }`
+        let doc_string = Self::document_item(item, |out| out.push_str(source));
+        // indexed as `vec!`, so that the macro is found before the module of the same name
+        let path = out.to_path_string_with(&format!("{name}!"));
+        out.index.push(path.into());
+        out.docs.push(doc_string.into());
+    }
+    fn visit_proc_macro(&self, id: &Id, out: &mut Output) {
+        let (item, proc_macro, name) = self.expect_proc_macro(id);
+        let doc_string = Self::document_item(item, |out| {
+            self.write_proc_macro(out, proc_macro, name);
+        });
+        let path = match proc_macro.kind {
+            MacroKind::Bang => out.to_path_string_with(&format!("{name}!")),
+            MacroKind::Attr | MacroKind::Derive => out.to_path_string_with(name),
+        };
+        out.index.push(path.into());
+        out.docs.push(doc_string.into());
+    }
+    fn visit_primitive(&self, id: &Id, out: &mut Output) {
+        let (item, primitive) = self.expect_primitive(id);
+        let name = primitive.name.as_str();
+        let doc_string = Self::document_item(item, |out| out.push_str(name));
+        let path = out.to_path_string_with(name);
+        out.index.push(path.into());
+        out.docs.push(doc_string.into());
+
+        // inherent methods and constants are indexed as `usize::MAX`, like those of structs.
+        // Some of the impls can be in another crate, e.g. when documenting std
+        out.segment_stack.push(name.to_owned());
+        for imp in &primitive.impls {
+            if self.krate.index.contains_key(imp) {
+                self.visit_impl(imp, out);
+            }
+        }
+        out.segment_stack.pop();
+    }
     fn visit_import(&self, id: &Id, out: &mut Output) {
         let (_, import) = self.expect_import(id);
         if let Some(id) = &import.id {
@@ -499,6 +634,273 @@ impl CrateCtxt {
         self.write_impls(out, impls, name);
     }
 
+    fn write_trait(
+        &self,
+        out: &mut String,
+        Trait {
+            is_auto,
+            is_unsafe,
+            items,
+            generics,
+            bounds,
+            implementations: _,
+        }: &Trait,
+        name: &str,
+    ) {
+        const MAX_PROVIDED_METHODS: usize = 10;
+
+        if *is_unsafe {
+            out.push_str("unsafe ");
+        }
+        if *is_auto {
+            out.push_str("auto ");
+        }
+        out.push_str("trait ");
+        out.push_str(name);
+        self.write_generics(out, generics);
+        if !bounds.is_empty() {
+            out.push_str(": ");
+            self.write_bounds(out, bounds);
+        }
+        out.push_str(" {\n");
+
+        let items = items.iter().map(|id| &self.krate.index[id]);
+
+        for item in items.clone() {
+            match &item.inner {
+                ItemEnum::AssocType {
+                    generics: _,
+                    bounds,
+                    default,
+                } => {
+                    out.push_str("  type ");
+                    out.push_str(item.name.as_deref().unwrap());
+                    if !bounds.is_empty() {
+                        out.push_str(": ");
+                        self.write_bounds(out, bounds);
+                    }
+                    if let Some(default) = default {
+                        out.push_str(" = ");
+                        self.write_type(out, default);
+                    }
+                    out.push_str(";\n");
+                }
+                ItemEnum::AssocConst { type_, default } => {
+                    out.push_str("  const ");
+                    out.push_str(item.name.as_deref().unwrap());
+                    out.push_str(": ");
+                    self.write_type(out, type_);
+                    if let Some(default) = default {
+                        out.push_str(" = ");
+                        out.push_str(default);
+                    }
+                    out.push_str(";\n");
+                }
+                _ => {}
+            }
+        }
+
+        let (provided, required): (Vec<_>, Vec<_>) = items
+            .filter_map(|item| match &item.inner {
+                ItemEnum::Function(function) => Some((item, function)),
+                _ => None,
+            })
+            .partition(|(_, function)| function.has_body);
+
+        if !required.is_empty() {
+            out.push_str("  // required methods\n");
+            for (item, function) in &required {
+                out.push_str("  ");
+                self.write_function(out, function, item.name.as_deref().unwrap());
+                out.push_str(";\n");
+            }
+        }
+
+        if !provided.is_empty() {
+            out.push_str("  // provided methods\n");
+            for (item, function) in provided.iter().take(MAX_PROVIDED_METHODS) {
+                out.push_str("  ");
+                self.write_function(out, function, item.name.as_deref().unwrap());
+                out.push_str(" { ... }\n");
+            }
+
+            if provided.len() > MAX_PROVIDED_METHODS {
+                let _ = writeln!(
+                    out,
+                    "  // {} more provided methods",
+                    provided.len() - MAX_PROVIDED_METHODS
+                );
+            }
+        }
+
+        out.push('}');
+    }
+
+    fn write_bounds(&self, out: &mut String, bounds: &[GenericBound]) {
+        for (index, bound) in bounds.iter().enumerate() {
+            if index != 0 {
+                out.push_str(" + ");
+            }
+            match bound {
+                GenericBound::Outlives(lt) => out.push_str(lt),
+                GenericBound::TraitBound {
+                    trait_,
+                    generic_params: _,
+                    modifier,
+                } => {
+                    if let TraitBoundModifier::Maybe = modifier {
+                        out.push('?');
+                    }
+                    self.write_path(out, trait_);
+                }
+            }
+        }
+    }
+
+    fn write_path(&self, out: &mut String, path: &Path) {
+        out.push_str(&path.name);
+
+        if let Some(args) = &path.args {
+            match &**args {
+                GenericArgs::AngleBracketed { args, .. } => {
+                    if !args.is_empty() {
+                        out.push('<');
+                        for (i, arg) in args.iter().enumerate() {
+                            if i != 0 {
+                                out.push_str(", ");
+                            }
+
+                            match arg {
+                                // rustdoc includes the `'` in lifetime names
+                                GenericArg::Lifetime(lt) => out.push_str(lt),
+                                GenericArg::Infer => out.push('_'),
+                                GenericArg::Type(ty) => self.write_type(out, ty),
+                                GenericArg::Const(c) => {
+                                    out.push_str("const ");
+                                    out.push_str(&c.expr);
+                                    out.push_str(": ");
+                                    self.write_type(out, &c.type_);
+                                }
+                            }
+                        }
+                        out.push('>');
+                    }
+                }
+                GenericArgs::Parenthesized { inputs, output } => {
+                    if !inputs.is_empty() {
+                        out.push('(');
+                        for (i, arg) in inputs.iter().enumerate() {
+                            if i != 0 {
+                                out.push_str(", ");
+                            }
+                            self.write_type(out, arg);
+                        }
+                        out.push(')');
+                    }
+                    if let Some(output) = output {
+                        out.push_str(" -> ");
+                        self.write_type(out, output);
+                    }
+                }
+            }
+        }
+    }
+
+    fn write_typedef(&self, out: &mut String, Typedef { type_, generics }: &Typedef, name: &str) {
+        out.push_str("type ");
+        out.push_str(name);
+        self.write_generics(out, generics);
+        out.push_str(" = ");
+        self.write_type(out, type_);
+        out.push(';');
+    }
+
+    fn write_constant(
+        &self,
+        out: &mut String,
+        Constant {
+            type_,
+            expr,
+            value,
+            is_literal: _,
+        }: &Constant,
+        name: &str,
+    ) {
+        out.push_str("const ");
+        out.push_str(name);
+        out.push_str(": ");
+        self.write_type(out, type_);
+        out.push_str(" = ");
+        out.push_str(expr);
+        out.push(';');
+
+        // e.g. `usize::MAX` is more useful with its value next to it
+        if let Some(value) = value
+            && value != expr
+        {
+            out.push_str(" // ");
+            out.push_str(value);
+        }
+    }
+
+    fn write_static(
+        &self,
+        out: &mut String,
+        Static {
+            type_,
+            mutable,
+            expr,
+        }: &Static,
+        name: &str,
+    ) {
+        out.push_str("static ");
+        if *mutable {
+            out.push_str("mut ");
+        }
+        out.push_str(name);
+        out.push_str(": ");
+        self.write_type(out, type_);
+        out.push_str(" = ");
+        out.push_str(expr);
+        out.push(';');
+    }
+
+    fn write_proc_macro(
+        &self,
+        out: &mut String,
+        ProcMacro { kind, helpers }: &ProcMacro,
+        name: &str,
+    ) {
+        match kind {
+            MacroKind::Bang => {
+                out.push_str(name);
+                out.push_str("!(...)");
+            }
+            MacroKind::Attr => {
+                out.push_str("#[");
+                out.push_str(name);
+                out.push(']');
+            }
+            MacroKind::Derive => {
+                out.push_str("#[derive(");
+                out.push_str(name);
+                out.push_str(")]");
+            }
+        }
+
+        if !helpers.is_empty() {
+            out.push_str("\n// helper attributes: ");
+            for (index, helper) in helpers.iter().enumerate() {
+                if index != 0 {
+                    out.push_str(", ");
+                }
+                out.push_str("#[");
+                out.push_str(helper);
+                out.push(']');
+            }
+        }
+    }
+
     fn write_function_decl(&self, out: &mut String, decl: &FnDecl) {
         for (i, (name, ty)) in decl.inputs.iter().enumerate() {
             if i != 0 {
@@ -564,11 +966,14 @@ impl CrateCtxt {
                 mutable,
                 type_,
             } => {
-                out.push_str(&format!(
-                    "&{}{}",
-                    lifetime.as_deref().unwrap_or(""),
-                    if *mutable { "mut " } else { "" },
-                ));
+                out.push('&');
+                if let Some(lifetime) = lifetime {
+                    out.push_str(lifetime);
+                    out.push(' ');
+                }
+                if *mutable {
+                    out.push_str("mut ");
+                }
                 self.write_type(out, type_);
             }
             Type::Tuple(tup) => {
@@ -619,56 +1024,7 @@ impl CrateCtxt {
                 }
                 self.write_type(out, type_);
             }
-            Type::ResolvedPath(path) => {
-                out.push_str(&path.name);
-
-                if let Some(args) = &path.args {
-                    match &**args {
-                        GenericArgs::AngleBracketed { args, .. } => {
-                            if !args.is_empty() {
-                                out.push('<');
-                                for (i, arg) in args.iter().enumerate() {
-                                    if i != 0 {
-                                        out.push_str(", ");
-                                    }
-
-                                    match arg {
-                                        GenericArg::Lifetime(lt) => {
-                                            out.push('\'');
-                                            out.push_str(lt);
-                                        }
-                                        GenericArg::Infer => out.push('_'),
-                                        GenericArg::Type(ty) => self.write_type(out, ty),
-                                        GenericArg::Const(c) => {
-                                            out.push_str("const ");
-                                            out.push_str(&c.expr);
-                                            out.push_str(": ");
-                                            self.write_type(out, &c.type_);
-                                        }
-                                    }
-                                }
-                                out.push('>');
-                            }
-                        }
-                        GenericArgs::Parenthesized { inputs, output } => {
-                            if !inputs.is_empty() {
-                                out.push('(');
-                                for (i, arg) in inputs.iter().enumerate() {
-                                    if i != 0 {
-                                        out.push_str(", ");
-                                    }
-                                    self.write_type(out, arg);
-                                }
-                                out.push(')');
-                            }
-                            if let Some(output) = output {
-                                out.push_str(" -> ");
-                                self.write_type(out, output);
-                            }
-                        }
-                    }
-                }
-            }
+            Type::ResolvedPath(path) => self.write_path(out, path),
             Type::QualifiedPath {
                 name,
                 args,
@@ -679,25 +1035,7 @@ impl CrateCtxt {
             }
             Type::ImplTrait(bounds) => {
                 out.push_str("impl ");
-                for (index, bound) in bounds.iter().enumerate() {
-                    if index != 0 {
-                        out.push_str(" + ");
-                    }
-                    match bound {
-                        GenericBound::Outlives(lt) => {
-                            out.push('\'');
-                            out.push_str(lt);
-                        }
-                        GenericBound::TraitBound {
-                            trait_,
-                            generic_params,
-                            modifier,
-                        } => {
-                            // TODO: dedupliate Path writing logic
-                            out.push_str(&trait_.name);
-                        }
-                    }
-                }
+                self.write_bounds(out, bounds);
             }
             _ => panic!("unknown type {:?}", ty),
         }
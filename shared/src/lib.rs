@@ -1,20 +1,208 @@
+use std::fmt::Write;
+use std::time::Duration;
+
 use serde::Deserialize;
 use serde::Serialize;
 
+/// Version of the protocol between bot and worker.
+/// Must be bumped whenever the encoding of any message changes.
+pub const PROTOCOL_VERSION: u32 = 14;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The code could not be parsed or compiled
+    Syntax,
+    /// The code threw an exception that was not caught
+    Exception,
+    /// A resource limit of the worker was exceeded
+    LimitExceeded,
+    /// Something went wrong in the worker itself
+    Internal,
+    /// The engine panicked. The message contains the panic message and a backtrace
+    Crash,
+}
+
+/// Byte range into the evaluated source code
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+pub struct Span {
+    pub lo: u32,
+    pub hi: u32,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct EvalError {
+    pub kind: ErrorKind,
+    /// The formatted error. For syntax errors this contains ANSI escape codes,
+    /// for exceptions it is the formatted exception value.
+    pub message: String,
+    pub spans: Vec<Span>,
+}
+
+impl EvalError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            spans: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct EvalOutput {
+    /// The formatted completion value, or the error
+    pub result: Result<String, EvalError>,
+    /// Everything that was logged to the console during evaluation
+    pub logs: String,
+    pub duration: Duration,
+    /// Only collected if [`EvalOptions::stats`] is set and the engine supports it
+    pub stats: Option<EvalStats>,
+}
+
+/// Where the time and memory of an evaluation went
+#[derive(Deserialize, Serialize, Debug)]
+pub struct EvalStats {
+    pub parse: Duration,
+    pub compile: Duration,
+    /// Running the code, including the event loop
+    pub execute: Duration,
+    /// `None` if the engine doesn't report its garbage collections
+    pub gc_cycles: Option<u64>,
+    /// Bytes allocated by the thread that ran the evaluation, before and after it
+    pub heap_before: usize,
+    pub heap_after: usize,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct BenchResult {
+    /// Index of the snippet in [`HostMessage::Bench`]
+    pub snippet: usize,
+    pub opt: Opt,
+    pub iterations: u64,
+    pub per_iteration: Result<Duration, EvalError>,
+    /// The snippet ran out of its share of the time budget,
+    /// so fewer iterations than planned were measured
+    pub partial: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    Eval,
+    DumpIr,
+    Sessions,
+    DumpAst,
+    DumpTokens,
+    DumpBytecode,
+    DumpIrDiff,
+    Bench,
+    Stats,
+    Wasm,
+}
+
+/// Script engines that a worker can embed
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EngineKind {
+    /// JavaScript
+    Dash,
+    Rhai,
+    /// JavaScript, used as a reference for dash
+    Boa,
+}
+
+/// What a worker supports, sent in response to [`HostMessage::Handshake`]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct WorkerInfo {
+    /// Compared against the bot's [`PROTOCOL_VERSION`]
+    pub protocol_version: u32,
+    pub engine: String,
+    pub engine_version: String,
+    pub features: Vec<Feature>,
+    pub opt_levels: Vec<Opt>,
+    pub engines: Vec<EngineKind>,
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub enum ClientMessage {
-    EvalResponse(Result<String, String>),
+    Handshake(WorkerInfo),
+    EvalResponse(EvalOutput),
+    SessionReset,
+    BenchResponse(Vec<BenchResult>),
+    /// Response to [`HostMessage::EvalSession`]
+    SessionEvalResponse {
+        output: EvalOutput,
+        /// The session didn't exist and was created for this message
+        new_session: bool,
+    },
 }
 
-#[derive(Deserialize, Serialize, Copy, Clone)]
+/// Identifies a REPL session. Thread and user IDs are kept apart,
+/// so that a user's session can never be mixed up with a thread's
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SessionId {
+    /// Shared by everyone in a thread
+    Thread(u64),
+    /// A user's own session outside of threads
+    User(u64),
+}
+
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Opt {
     None,
     Basic,
     Aggressive,
 }
 
+/// How code passed to [`HostMessage::Eval`] and [`HostMessage::EvalSession`] is evaluated
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+pub struct EvalOptions {
+    pub opt: Opt,
+    /// Colorize formatted values with ANSI escape codes
+    pub colors: bool,
+    /// Seeds `Math.random` and replaces the clock with a virtual one, so that the output is reproducible
+    pub seed: Option<u64>,
+    /// Collect [`EvalStats`]
+    pub stats: bool,
+    /// How long the host waits for the response.
+    /// Pending timers are only waited for as long as this allows
+    pub timeout: Duration,
+}
+
 #[derive(Deserialize, Serialize)]
 pub enum HostMessage {
-    Eval(String, Opt),
+    /// Asks the worker for its [`WorkerInfo`], containing the host's [`PROTOCOL_VERSION`].
+    /// Messages are decoded as a whole, so if the versions differ this message or its response
+    /// may not decode at all. The bot treats that as a version mismatch too.
+    Handshake(u32),
+    Eval(EngineKind, String, EvalOptions),
     DumpIr(String, Opt),
+    /// Evaluates code in the long-lived VM of the given session, creating it if it doesn't exist yet
+    EvalSession(SessionId, String, EvalOptions),
+    ResetSession(SessionId),
+    DumpAst(String),
+    DumpTokens(String),
+    /// Bytecode, one decoded instruction per line with its offset, and the constant pool
+    DumpBytecode(String, Opt),
+    /// Diff of the decompiled bytecode at two optimization levels
+    DumpIrDiff(String, Opt, Opt),
+    /// Benchmarks every snippet at every opt level, taking at most about the given time.
+    /// Every snippet gets an equal share of it, and what it didn't use goes to the next ones
+    Bench(Vec<String>, Vec<Opt>, Duration),
+    /// Instantiates a WebAssembly module (WAT or base64 encoded binary)
+    /// and calls the exported function with the arguments
+    EvalWasm(String, String, Vec<String>),
+}
+
+/// Creates a line diff of two strings that can be rendered in a ```diff codeblock
+pub fn diff_lines(left: &str, right: &str) -> String {
+    let mut output = String::new();
+
+    for diff in diff::lines(left.trim_end(), right.trim_end()) {
+        let _ = match diff {
+            diff::Result::Left(l) => writeln!(output, "- {l}"),
+            diff::Result::Both(l, _) => writeln!(output, "  {l}"),
+            diff::Result::Right(r) => writeln!(output, "+ {r}"),
+        };
+    }
+
+    output
 }
@@ -0,0 +1,20 @@
+//! Exposes the revision of the dash engine that the worker is built against as `DASH_VERSION`
+
+use std::fs;
+
+fn main() {
+    println!("cargo:rerun-if-changed=../Cargo.lock");
+
+    let version = fs::read_to_string("../Cargo.lock")
+        .ok()
+        .and_then(|lock| {
+            let (_, package) = lock.split_once("name = \"dash_vm\"")?;
+            let source = package
+                .lines()
+                .find_map(|line| line.strip_prefix("source = "))?;
+            Some(source.trim_matches('"').rsplit('#').next()?.to_owned())
+        })
+        .unwrap_or_else(|| "unknown".into());
+
+    println!("cargo:rustc-env=DASH_VERSION={version}");
+}
@@ -0,0 +1,26 @@
+// Replaces the global console with one that records its arguments into a buffer.
+// Evaluates to a function that returns another function, which drains the buffer
+// and is called by the worker after every evaluation.
+// The arguments of every call are formatted by the worker.
+
+(function () {
+    let buffer = [];
+
+    function log(...args) {
+        buffer.push(args);
+    }
+
+    globalThis.console = {
+        log,
+        info: log,
+        debug: log,
+        warn: log,
+        error: log
+    };
+
+    return function drain() {
+        const calls = buffer;
+        buffer = [];
+        return calls;
+    };
+})
@@ -0,0 +1,107 @@
+// Timers and promise settling for the event loop that the worker drives after evaluating code.
+// Evaluates to a function that takes the completion value and returns a `poll` function:
+//  - called without arguments, it runs all due timers and returns how many milliseconds to wait
+//    for the next one, or -1 if there is nothing left to wait for.
+//    Only timers of the current evaluation are waited for. Timers left over from earlier
+//    evaluations of a session (like an interval that was never cleared) still run meanwhile,
+//    but would otherwise hold up every later evaluation
+//  - called with `true` once the worker is done, it returns the settled value or throws the rejection reason.
+//    A promise that is still pending is returned as is.
+
+(function () {
+    let timers = [];
+    let nextId = 1;
+    // incremented when the event loop of an evaluation ends, to tell its timers apart from newer ones
+    let evaluation = 0;
+
+    function addTimer(callback, delay, args, repeat) {
+        const id = nextId++;
+        delay = Math.max(Number(delay) || 0, 0);
+        timers.push({ id, callback, args, delay, repeat, evaluation, due: Date.now() + delay });
+        return id;
+    }
+
+    function clearTimer(id) {
+        timers = timers.filter(timer => timer.id !== id);
+    }
+
+    globalThis.setTimeout = (callback, delay, ...args) => addTimer(callback, delay, args, false);
+    globalThis.setInterval = (callback, delay, ...args) => addTimer(callback, delay, args, true);
+    globalThis.clearTimeout = clearTimer;
+    globalThis.clearInterval = clearTimer;
+
+    function runDueTimers() {
+        const now = Date.now();
+        const due = timers
+            .filter(timer => timer.due <= now)
+            .sort((a, b) => a.due - b.due);
+
+        for (const timer of due) {
+            // cleared by a callback that ran before it in this batch
+            if (!timers.includes(timer)) {
+                continue;
+            }
+
+            if (timer.repeat) {
+                timer.due = now + Math.max(timer.delay, 1);
+            } else {
+                clearTimer(timer.id);
+            }
+
+            timer.callback(...timer.args);
+        }
+    }
+
+    function nextDelay() {
+        if (!timers.some(timer => timer.evaluation === evaluation)) {
+            return -1;
+        }
+
+        let next = Infinity;
+        for (const timer of timers) {
+            next = Math.min(next, timer.due);
+        }
+
+        return Math.max(next - Date.now(), 0);
+    }
+
+    return function settle(value) {
+        let state = 'fulfilled';
+        let result = value;
+
+        if (value instanceof Promise) {
+            state = 'pending';
+            value.then(
+                v => {
+                    state = 'fulfilled';
+                    result = v;
+                },
+                e => {
+                    state = 'rejected';
+                    result = e;
+                }
+            );
+        }
+
+        return function poll(finish) {
+            if (finish) {
+                evaluation++;
+                if (state === 'rejected') {
+                    throw result;
+                }
+
+                return result;
+            }
+
+            try {
+                runDueTimers();
+            } catch (err) {
+                // the worker stops driving the event loop when a timer throws
+                evaluation++;
+                throw err;
+            }
+
+            return nextDelay();
+        };
+    };
+})
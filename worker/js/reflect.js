@@ -0,0 +1,35 @@
+// Operations for formatting values that boa doesn't expose to the embedder.
+// Evaluates to an object of helper functions.
+
+({
+    // [key, kind, value] for every own enumerable string key, where kind is 'value', 'get', 'set' or 'getset'
+    properties(object) {
+        return Object.keys(object).map(key => {
+            const descriptor = Object.getOwnPropertyDescriptor(object, key);
+
+            if (descriptor.get && descriptor.set) {
+                return [key, 'getset'];
+            }
+            if (descriptor.get) {
+                return [key, 'get'];
+            }
+            if (descriptor.set) {
+                return [key, 'set'];
+            }
+
+            return [key, 'value', descriptor.value];
+        });
+    },
+
+    // the first `limit` entries of a map or set
+    toArray(value, limit) {
+        const entries = [];
+        for (const entry of value) {
+            if (entries.length >= limit) {
+                break;
+            }
+            entries.push(entry);
+        }
+        return entries;
+    }
+})
@@ -0,0 +1,41 @@
+// Step budget for loops, see `steps.rs` for how loop conditions are rewritten to call `__step()`.
+// Evaluates to a function that takes a function checking the worker's other limits, which throws
+// once one of them was exceeded. It returns a `budget` function that the worker calls with the
+// number of steps before every evaluation and with `Infinity` after it, and that returns
+// whether the budget ran out since the previous call.
+
+(function (checkLimits) {
+    // steps between two calls to `checkLimits`
+    const CHECK_INTERVAL = 1024;
+    let remaining = Infinity;
+    let ranOut = false;
+    let sinceCheck = 0;
+
+    // user code shares the global scope, so it must not be able to replace this or see it
+    // when enumerating the global object
+    Object.defineProperty(globalThis, '__step', {
+        value: function () {
+            if (--remaining < 0) {
+                ranOut = true;
+                throw new Error('step limit exceeded');
+            }
+
+            if (++sinceCheck === CHECK_INTERVAL) {
+                sinceCheck = 0;
+                checkLimits();
+            }
+
+            return true;
+        },
+        writable: false,
+        enumerable: false,
+        configurable: false,
+    });
+
+    return function budget(steps) {
+        const result = ranOut;
+        remaining = steps;
+        ranOut = false;
+        return result;
+    };
+})
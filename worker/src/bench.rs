@@ -0,0 +1,169 @@
+//! Micro-benchmarks of JavaScript snippets, mirroring the Rust benchmark template of the bot
+
+use std::time::Duration;
+use std::time::Instant;
+
+use shared::BenchResult;
+use shared::ErrorKind;
+use shared::EvalError;
+
+use crate::crash;
+use crate::engine::dash::Dash;
+use crate::limits;
+use crate::steps;
+
+/// Roughly how long all rounds of one snippet at one opt level should take
+const TARGET_TIME: Duration = Duration::from_millis(300);
+const ROUNDS: u32 = 3;
+/// Single runs before the rounds, whose median is used to pick the iteration count
+const WARMUP_RUNS: usize = 5;
+const MAX_ITERATIONS: u64 = 1_000_000;
+/// About how often the deadline is checked while measuring
+const DEADLINE_CHECK_INTERVAL: Duration = Duration::from_millis(1);
+
+/// What was measured for one snippet
+struct Measurement {
+    iterations: u64,
+    per_iteration: Duration,
+    /// Whether the snippet ran out of time before all iterations were done
+    partial: bool,
+}
+
+/// Benchmarks every snippet at every opt level within about `budget`
+pub fn run(snippets: &[String], opts: &[shared::Opt], budget: Duration) -> Vec<BenchResult> {
+    let deadline = Instant::now() + budget;
+    let mut results = Vec::new();
+    let mut remaining = snippets.len() * opts.len();
+
+    for &opt in opts {
+        for (snippet, code) in snippets.iter().enumerate() {
+            // an equal share of what is left, so a snippet that finishes early leaves more time
+            // for the others and a slow one can't take away the results of all of them
+            let budget = deadline.saturating_duration_since(Instant::now()) / remaining as u32;
+            remaining -= 1;
+
+            // every snippet gets a fresh VM, so the others can still run after a crash
+            let result = crash::catch(|| {
+                limits::run_limited(limits::MAX_HEAP_SIZE, || bench_one(code, opt, budget))
+            });
+            let (iterations, per_iteration, partial) = match result {
+                Ok(Ok(Ok(measurement))) => (
+                    measurement.iterations,
+                    Ok(measurement.per_iteration),
+                    measurement.partial,
+                ),
+                Ok(Ok(Err(err))) => (0, Err(err), false),
+                Ok(Err(err)) => (
+                    0,
+                    Err(EvalError::new(ErrorKind::LimitExceeded, err.to_string())),
+                    false,
+                ),
+                Err(report) => (0, Err(EvalError::new(ErrorKind::Crash, report)), false),
+            };
+
+            results.push(BenchResult {
+                snippet,
+                opt,
+                iterations,
+                per_iteration,
+                partial,
+            });
+        }
+    }
+
+    results
+}
+
+fn memory_exceeded() -> EvalError {
+    EvalError::new(
+        ErrorKind::LimitExceeded,
+        limits::LimitExceeded::Memory.to_string(),
+    )
+}
+
+/// Measures the average time of one call of the snippet, taking at most about `budget`
+fn bench_one(code: &str, opt: shared::Opt, budget: Duration) -> Result<Measurement, EvalError> {
+    let deadline = Instant::now() + budget;
+    let mut session = Dash::new();
+    let function = session.eval_function(code, opt)?;
+
+    // The first run counts loop iterations, so that a snippet that takes far too long can be
+    // stopped once it is past the deadline. The others don't, so that only the snippet is measured.
+    // If the rewritten code doesn't compile, the snippet has to be trusted to finish
+    let checked = session.eval_function(&steps::instrument(code), opt).ok();
+    let start = Instant::now();
+    limits::run_with_deadline(deadline, || {
+        session.call_limited(checked.as_ref().unwrap_or(&function))
+    })
+    .map_err(|err| {
+        if Instant::now() >= deadline {
+            EvalError::new(
+                ErrorKind::LimitExceeded,
+                format!(
+                    "a single run took longer than this snippet's share of the time ({budget:?})"
+                ),
+            )
+        } else {
+            err
+        }
+    })?;
+    if limits::memory_exceeded() {
+        return Err(memory_exceeded());
+    }
+
+    // Calibrate the iteration count based on a few single runs, which also serve as a warmup.
+    // The median ignores outliers like the first run being slow
+    let mut warmup = vec![start.elapsed()];
+    while warmup.len() < WARMUP_RUNS && Instant::now() < deadline {
+        let start = Instant::now();
+        session.call(&function)?;
+        warmup.push(start.elapsed());
+    }
+    warmup.sort_unstable();
+    let single = warmup[warmup.len() / 2].max(Duration::from_nanos(1));
+
+    // no time for the rounds, so the warmup is all there is
+    if warmup.len() < WARMUP_RUNS {
+        return Ok(Measurement {
+            iterations: warmup.len() as u64,
+            per_iteration: warmup.iter().sum::<Duration>() / warmup.len() as u32,
+            partial: true,
+        });
+    }
+
+    let target = TARGET_TIME.min(deadline.saturating_duration_since(Instant::now()));
+    let iterations = (target.as_nanos() / ROUNDS as u128 / single.as_nanos())
+        .clamp(1, MAX_ITERATIONS as u128) as u64;
+    // reading the clock after every iteration would be measured too,
+    // so it is only read about every millisecond
+    let check_interval = (DEADLINE_CHECK_INTERVAL.as_nanos() / single.as_nanos()).max(1) as u64;
+
+    let mut total = Duration::ZERO;
+    let mut measured = 0;
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        for iteration in 1..=iterations {
+            session.call(&function)?;
+            // the snippets run as they are, without a step budget that would check this
+            if limits::memory_exceeded() {
+                return Err(memory_exceeded());
+            }
+
+            if iteration % check_interval == 0 && Instant::now() >= deadline {
+                return Ok(Measurement {
+                    iterations: measured + iteration,
+                    per_iteration: (total + start.elapsed()) / (measured + iteration) as u32,
+                    partial: true,
+                });
+            }
+        }
+        total += start.elapsed();
+        measured += iterations;
+    }
+
+    Ok(Measurement {
+        iterations,
+        per_iteration: total / measured as u32,
+        partial: false,
+    })
+}
@@ -0,0 +1,78 @@
+//! Sources of nondeterminism exposed to JavaScript: `Math.random` and the current time.
+//!
+//! Normally these come from the OS. In deterministic mode, `Math.random` uses a PRNG seeded by
+//! the host and the clock is virtual: it starts at [`VIRTUAL_EPOCH_MILLIS`] and only moves when
+//! the event loop advances it to the next timer, so the same code always produces the same output.
+//!
+//! `VmParams` only takes plain function pointers, so the state lives in a thread local.
+
+use std::cell::RefCell;
+use std::time::SystemTime;
+
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand::SeedableRng;
+
+/// 2000-01-01T00:00:00Z
+pub const VIRTUAL_EPOCH_MILLIS: u64 = 946_684_800_000;
+
+struct Deterministic {
+    rng: StdRng,
+    now_millis: f64,
+}
+
+thread_local! {
+    static DETERMINISTIC: RefCell<Option<Deterministic>> = RefCell::new(None);
+}
+
+/// Makes everything run until [`Guard`] is dropped deterministic
+pub fn deterministic(seed: u64) -> Guard {
+    DETERMINISTIC.with(|d| {
+        *d.borrow_mut() = Some(Deterministic {
+            rng: StdRng::seed_from_u64(seed),
+            now_millis: VIRTUAL_EPOCH_MILLIS as f64,
+        })
+    });
+    Guard
+}
+
+/// Switches back to the OS clock and RNG when dropped
+pub struct Guard;
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        DETERMINISTIC.with(|d| *d.borrow_mut() = None);
+    }
+}
+
+pub fn is_deterministic() -> bool {
+    DETERMINISTIC.with(|d| d.borrow().is_some())
+}
+
+/// Moves the virtual clock forward. Does nothing if the clock is not virtual
+pub fn advance(millis: f64) {
+    DETERMINISTIC.with(|d| {
+        if let Some(d) = &mut *d.borrow_mut() {
+            d.now_millis += millis;
+        }
+    });
+}
+
+pub fn random() -> f64 {
+    DETERMINISTIC.with(|d| match &mut *d.borrow_mut() {
+        Some(d) => d.rng.gen(),
+        None => rand::random(),
+    })
+}
+
+pub fn now_millis() -> u64 {
+    DETERMINISTIC.with(|d| match &*d.borrow() {
+        Some(d) => d.now_millis as u64,
+        None => SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+            .try_into()
+            .unwrap(),
+    })
+}
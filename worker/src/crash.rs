@@ -0,0 +1,44 @@
+//! Panics in an engine are caught per job and reported to the bot as [`ErrorKind::Crash`],
+//! with the panic message and a backtrace, instead of taking the whole worker down.
+//!
+//! Crashes that can't be unwound (aborts, stack overflows) still kill the process.
+//! The bot tells those apart from timeouts by whether its own timer for the job ran out first,
+//! see `WorkerPool::send_timeout`.
+//!
+//! [`ErrorKind::Crash`]: shared::ErrorKind::Crash
+
+use std::backtrace::Backtrace;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::Mutex;
+
+/// Message and backtrace of the last panic, recorded by the panic hook
+static LAST_PANIC: Mutex<Option<String>> = Mutex::new(None);
+
+/// Installs the panic hook that records panics for [`catch`]. Must be called once at startup
+pub fn install() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let backtrace = Backtrace::force_capture();
+        let report = format!("{info}\n\nstack backtrace:\n{backtrace}");
+        if let Ok(mut last_panic) = LAST_PANIC.lock() {
+            *last_panic = Some(report);
+        }
+
+        // still log it to stderr
+        default_hook(info);
+    }));
+}
+
+/// Runs `f`, returning the panic message and backtrace if it panics.
+///
+/// Whatever `f` captured by reference must not be used again after a panic.
+pub fn catch<R>(f: impl FnOnce() -> R) -> Result<R, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|_| {
+        LAST_PANIC
+            .lock()
+            .ok()
+            .and_then(|mut last_panic| last_panic.take())
+            .unwrap_or_else(|| "the engine panicked, but no panic message was recorded".into())
+    })
+}
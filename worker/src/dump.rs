@@ -0,0 +1,103 @@
+//! Debugging views of the different compilation stages of the dash engine
+
+use std::fmt::Write;
+
+use dash_compiler::FunctionCompiler;
+use dash_decompiler::decompile;
+use dash_lexer::Lexer;
+use dash_middle::interner::StringInterner;
+use dash_parser::Parser;
+use shared::ErrorKind;
+use shared::EvalError;
+
+use crate::engine::dash::shared_opt_to_dash_opt;
+use crate::engine::dash::syntax_error;
+
+/// Decompiled bytecode
+pub fn ir(code: &str, opt: shared::Opt) -> Result<String, EvalError> {
+    let mut interner = StringInterner::new();
+    match FunctionCompiler::compile_str(&mut interner, code, shared_opt_to_dash_opt(opt)) {
+        Ok(v) => decompile(&interner, &v.cp, &v.instructions)
+            .map_err(|x| EvalError::new(ErrorKind::Internal, x.to_string())),
+        Err(errors) => Err(syntax_error(code, errors)),
+    }
+}
+
+/// Line diff of the decompiled bytecode at two optimization levels
+pub fn ir_diff(code: &str, left: shared::Opt, right: shared::Opt) -> Result<String, EvalError> {
+    let left = ir(code, left)?;
+    let right = ir(code, right)?;
+    Ok(shared::diff_lines(&left, &right))
+}
+
+/// Lexer output, one token per line, prefixed with its offset in the source
+pub fn tokens(code: &str) -> Result<String, EvalError> {
+    let mut interner = StringInterner::new();
+    let tokens = Lexer::new(&mut interner, code)
+        .scan_all()
+        .map_err(|errors| syntax_error(code, errors))?;
+
+    let mut output = String::new();
+    for token in tokens {
+        let _ = writeln!(output, "{:>5} {:?}", token.span.lo, token.ty);
+    }
+    Ok(output)
+}
+
+/// The parsed AST
+pub fn ast(code: &str) -> Result<String, EvalError> {
+    let mut interner = StringInterner::new();
+    let tokens = Lexer::new(&mut interner, code)
+        .scan_all()
+        .map_err(|errors| syntax_error(code, errors))?;
+    let ast = Parser::new(&mut interner, code, tokens)
+        .parse_all()
+        .map_err(|errors| syntax_error(code, errors))?;
+
+    Ok(format!("{ast:#?}"))
+}
+
+/// Bytecode of the top level function, one instruction per line with its offset and raw bytes,
+/// followed by its constant pool
+pub fn bytecode(code: &str, opt: shared::Opt) -> Result<String, EvalError> {
+    /// Opcode and operands of the longest instruction
+    const MAX_INSTRUCTION_LEN: usize = 8;
+
+    let mut interner = StringInterner::new();
+    let compiled = FunctionCompiler::compile_str(&mut interner, code, shared_opt_to_dash_opt(opt))
+        .map_err(|errors| syntax_error(code, errors))?;
+
+    // The decompiler is what knows the operands of every instruction, so this decompiles ever
+    // longer prefixes until one of them decodes, which is the instruction with all of its operands.
+    // Returns the length of the instruction and how the decompiler shows it
+    let decode = |instructions: &[u8]| {
+        (1..=instructions.len().min(MAX_INSTRUCTION_LEN)).find_map(|len| {
+            let decompiled = decompile(&interner, &compiled.cp, &instructions[..len]).ok()?;
+            // nested functions in the constant pool are decompiled after the instruction
+            let decoded = decompiled.lines().next().unwrap_or_default().trim();
+            Some((len, decoded.to_owned()))
+        })
+    };
+
+    let mut output = String::from("; instructions\n");
+    let mut offset = 0;
+    while offset < compiled.instructions.len() {
+        // not something the decompiler knows about, so there's no telling where the next one starts
+        let (len, decoded) =
+            decode(&compiled.instructions[offset..]).unwrap_or_else(|| (1, "<unknown>".into()));
+
+        let bytes: String = compiled.instructions[offset..offset + len]
+            .iter()
+            .map(|byte| format!("{byte:02x} "))
+            .collect();
+        let _ = writeln!(output, "{offset:04x}: {bytes:24}{decoded}");
+        offset += len;
+    }
+
+    output.push_str("\n; constant pool\n");
+    for (index, constant) in compiled.cp.iter().enumerate() {
+        let _ = writeln!(output, "#{index}: {constant:?}");
+    }
+
+    Ok(output)
+}
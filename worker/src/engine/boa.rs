@@ -0,0 +1,426 @@
+//! The boa JavaScript engine, used as a reference to compare dash against
+
+use std::time::Duration;
+use std::time::Instant;
+
+use boa_engine::builtins::error::ErrorObject;
+use boa_engine::builtins::promise::PromiseState;
+use boa_engine::context::HostHooks;
+use boa_engine::object::builtins::JsMap;
+use boa_engine::object::builtins::JsPromise;
+use boa_engine::object::builtins::JsSet;
+use boa_engine::Context;
+use boa_engine::JsError;
+use boa_engine::JsNativeError;
+use boa_engine::JsObject;
+use boa_engine::JsString;
+use boa_engine::JsValue;
+use boa_engine::NativeFunction;
+use boa_engine::Script;
+use boa_engine::Source;
+use shared::ErrorKind;
+use shared::EvalError;
+use shared::EvalOptions;
+use shared::EvalOutput;
+
+use crate::clock;
+use crate::inspect;
+use crate::inspect::InspectOptions;
+use crate::inspect::Kind;
+use crate::inspect::Property;
+use crate::inspect::Reflect;
+use crate::limits;
+use crate::steps;
+
+use super::drive_event_loop;
+use super::Engine;
+
+/// Makes `Date` use the same clock as dash, so that deterministic mode works the same
+struct Hooks;
+
+impl HostHooks for Hooks {
+    fn utc_now(&self) -> i64 {
+        clock::now_millis() as i64
+    }
+}
+
+static HOOKS: Hooks = Hooks;
+
+/// A context together with the helper functions that were evaluated into it
+pub struct Boa {
+    context: Context,
+    /// Helpers from `reflect.js`, used by [`BoaReflect`]
+    helpers: JsObject,
+    drain_logs: JsObject,
+    settle: JsObject,
+    /// Sets the step budget, see `steps.js`
+    budget: JsObject,
+}
+
+fn eval_prelude(context: &mut Context, code: &str) -> JsObject {
+    match context.eval(Source::from_bytes(code)) {
+        Ok(JsValue::Object(object)) => object,
+        _ => unreachable!("prelude did not evaluate to an object"),
+    }
+}
+
+fn call(context: &mut Context, function: &JsObject, args: &[JsValue]) -> Result<JsValue, JsError> {
+    function.call(&JsValue::undefined(), args, context)
+}
+
+impl Boa {
+    pub fn new() -> Self {
+        const REFLECT_CODE: &str = include_str!("../../js/reflect.js");
+        const CONSOLE_CODE: &str = include_str!("../../js/console.js");
+        const EVENT_LOOP_CODE: &str = include_str!("../../js/event_loop.js");
+        const STEPS_CODE: &str = include_str!("../../js/steps.js");
+
+        let mut context = Context::builder()
+            .host_hooks(&HOOKS)
+            .build()
+            .expect("failed to create boa context");
+
+        // boa doesn't let the embedder choose the random number generator, so replace the function
+        let random = NativeFunction::from_fn_ptr(|_, _, _| Ok(JsValue::new(clock::random())))
+            .to_js_function(&context.realm().clone());
+        let math = context
+            .global_object()
+            .get(JsString::from("Math"), &mut context)
+            .ok()
+            .and_then(|math| math.as_object().cloned())
+            .expect("Math is missing");
+        math.set(JsString::from("random"), random, false, &mut context)
+            .expect("failed to replace Math.random");
+
+        let helpers = eval_prelude(&mut context, REFLECT_CODE);
+
+        let setup_console = eval_prelude(&mut context, CONSOLE_CODE);
+        let Ok(JsValue::Object(drain_logs)) = call(&mut context, &setup_console, &[]) else {
+            unreachable!()
+        };
+
+        let setup_event_loop = eval_prelude(&mut context, EVENT_LOOP_CODE);
+        let Ok(JsValue::Object(settle)) = call(&mut context, &setup_event_loop, &[]) else {
+            unreachable!()
+        };
+
+        let check_limits = NativeFunction::from_fn_ptr(|_, _, _| {
+            if limits::memory_exceeded() {
+                Err(JsNativeError::range()
+                    .with_message("memory limit exceeded")
+                    .into())
+            } else {
+                Ok(JsValue::undefined())
+            }
+        })
+        .to_js_function(&context.realm().clone());
+        let setup_steps = eval_prelude(&mut context, STEPS_CODE);
+        let Ok(JsValue::Object(budget)) = call(&mut context, &setup_steps, &[check_limits.into()])
+        else {
+            unreachable!()
+        };
+
+        Self {
+            context,
+            helpers,
+            drain_logs,
+            settle,
+            budget,
+        }
+    }
+
+    /// Gives the next evaluation `steps` loop iterations.
+    /// Returns whether the previous evaluation ran out of them
+    fn set_step_budget(&mut self, steps: f64) -> bool {
+        call(&mut self.context, &self.budget, &[JsValue::new(steps)])
+            .map_or(false, |ran_out| ran_out.to_boolean())
+    }
+
+    /// Returns everything that was logged to the console since the last call
+    fn drain_logs(&mut self, options: InspectOptions) -> String {
+        let Ok(calls) = call(&mut self.context, &self.drain_logs, &[]) else {
+            return String::new();
+        };
+
+        let mut reflect = BoaReflect {
+            context: &mut self.context,
+            helpers: &self.helpers,
+        };
+        inspect::format_console_calls(&mut reflect, &calls, options)
+    }
+
+    /// Drives the event loop until the value (if it is a promise) has settled
+    /// and no timers are left, or the time budget is used up. Then formats the settled value.
+    fn run_event_loop(
+        &mut self,
+        value: JsValue,
+        options: InspectOptions,
+        started: Instant,
+        timeout: Duration,
+    ) -> Result<String, EvalError> {
+        let context = &mut self.context;
+        let helpers = &self.helpers;
+        let Ok(JsValue::Object(poll)) = call(context, &self.settle, &[value]) else {
+            return Err(EvalError::new(
+                ErrorKind::Internal,
+                "failed to set up the event loop",
+            ));
+        };
+
+        drive_event_loop(started, timeout, || {
+            context.run_jobs();
+
+            match call(context, &poll, &[]) {
+                Ok(delay) => Ok(delay.to_number(context).unwrap_or(-1.0)),
+                // a timer callback threw
+                Err(err) => Err(exception_error(context, helpers, err, options)),
+            }
+        })?;
+
+        context.run_jobs();
+
+        match call(context, &poll, &[JsValue::Boolean(true)]) {
+            Ok(value) => Ok(fmt_value(context, helpers, value, options)),
+            Err(err) => Err(exception_error(context, helpers, err, options)),
+        }
+    }
+}
+
+impl Engine for Boa {
+    fn eval(&mut self, code: &str, options: EvalOptions) -> EvalOutput {
+        let _deterministic = options.seed.map(clock::deterministic);
+        let inspect_options = InspectOptions {
+            colors: options.colors,
+            ..InspectOptions::default()
+        };
+        self.set_step_budget(steps::MAX_STEPS as f64);
+        let start = Instant::now();
+        // syntax errors are reported for the code as it was written
+        let instrumented = steps::instrument(code);
+        let script = Script::parse(Source::from_bytes(&instrumented), None, &mut self.context)
+            .or_else(|_| Script::parse(Source::from_bytes(code), None, &mut self.context));
+        // boa has no opt levels
+        let mut result = match script {
+            Ok(script) => match script.evaluate(&mut self.context) {
+                Ok(value) => self.run_event_loop(value, inspect_options, start, options.timeout),
+                Err(err) => Err(exception_error(
+                    &mut self.context,
+                    &self.helpers,
+                    err,
+                    inspect_options,
+                )),
+            },
+            Err(err) => Err(EvalError::new(ErrorKind::Syntax, err.to_string())),
+        };
+        let duration = start.elapsed();
+        if self.set_step_budget(f64::INFINITY) {
+            result = Err(steps::limit_exceeded());
+        }
+
+        EvalOutput {
+            result,
+            logs: self.drain_logs(inspect_options),
+            duration,
+            stats: None,
+        }
+    }
+}
+
+fn fmt_value(
+    context: &mut Context,
+    helpers: &JsObject,
+    value: JsValue,
+    options: InspectOptions,
+) -> String {
+    let mut reflect = BoaReflect { context, helpers };
+    inspect::inspect(&mut reflect, &value, options)
+}
+
+fn exception_error(
+    context: &mut Context,
+    helpers: &JsObject,
+    err: JsError,
+    options: InspectOptions,
+) -> EvalError {
+    let value = err.to_opaque(context);
+    EvalError::new(
+        ErrorKind::Exception,
+        fmt_value(context, helpers, value, options),
+    )
+}
+
+/// [`Reflect`] for boa values.
+///
+/// Own properties and map and set entries are read through the helper functions of `reflect.js`,
+/// because boa doesn't expose them to the embedder.
+pub struct BoaReflect<'a> {
+    pub context: &'a mut Context,
+    pub helpers: &'a JsObject,
+}
+
+impl BoaReflect<'_> {
+    fn get(&mut self, value: &JsValue, key: &str) -> Option<JsValue> {
+        value
+            .as_object()?
+            .get(JsString::from(key), self.context)
+            .ok()
+    }
+
+    fn get_string(&mut self, value: &JsValue, key: &str) -> Option<String> {
+        let value = self.get(value, key)?;
+        let string = value.to_string(self.context).ok()?;
+        Some(string.to_std_string_escaped())
+    }
+
+    fn call_helper(&mut self, name: &str, args: &[JsValue]) -> Option<JsValue> {
+        let helper = self.helpers.get(JsString::from(name), self.context).ok()?;
+        helper
+            .as_callable()?
+            .call(&JsValue::undefined(), args, self.context)
+            .ok()
+    }
+}
+
+impl Reflect for BoaReflect<'_> {
+    type Value = JsValue;
+
+    fn kind(&mut self, value: &JsValue) -> Kind {
+        match value {
+            JsValue::Undefined => Kind::Undefined,
+            JsValue::Null => Kind::Null,
+            JsValue::Boolean(b) => Kind::Boolean(*b),
+            JsValue::Integer(n) => Kind::Number(f64::from(*n)),
+            JsValue::Rational(n) => Kind::Number(*n),
+            JsValue::String(s) => Kind::String(s.to_std_string_escaped()),
+            JsValue::BigInt(n) => Kind::BigInt(n.to_string()),
+            JsValue::Symbol(s) => Kind::Symbol(
+                s.description()
+                    .map(|description| description.to_std_string_escaped())
+                    .unwrap_or_default(),
+            ),
+            JsValue::Object(o) => {
+                if o.is_callable() {
+                    Kind::Function(self.get_string(value, "name").unwrap_or_default())
+                } else if o.is::<ErrorObject>() {
+                    // boa has no stack traces, so this is just `Name: message`
+                    let stack = self
+                        .get(value, "stack")
+                        .and_then(|stack| stack.as_string().map(|s| s.to_std_string_escaped()));
+                    Kind::Error(
+                        stack
+                            .or_else(|| {
+                                let string = value.to_string(self.context).ok()?;
+                                Some(string.to_std_string_escaped())
+                            })
+                            .unwrap_or_default(),
+                    )
+                } else if o.is_array() {
+                    Kind::Array
+                } else if JsMap::from_object(o.clone()).is_ok() {
+                    Kind::Map
+                } else if JsSet::from_object(o.clone()).is_ok() {
+                    Kind::Set
+                } else if JsPromise::from_object(o.clone()).is_ok() {
+                    Kind::Promise
+                } else {
+                    Kind::Object
+                }
+            }
+        }
+    }
+
+    fn identity(&mut self, value: &JsValue) -> usize {
+        match value {
+            JsValue::Object(o) => {
+                let ptr: *const _ = o.as_ref();
+                ptr.cast::<()>() as usize
+            }
+            _ => 0,
+        }
+    }
+
+    fn class_name(&mut self, value: &JsValue) -> Option<String> {
+        let constructor = self.get(value, "constructor")?;
+        let name = self.get_string(&constructor, "name")?;
+        (name != "Object" && !name.is_empty()).then_some(name)
+    }
+
+    fn properties(&mut self, value: &JsValue) -> Vec<(String, Property<JsValue>)> {
+        let Some(entries) = self.call_helper("properties", &[value.clone()]) else {
+            return Vec::new();
+        };
+
+        self.array_elements(&entries, usize::MAX)
+            .into_iter()
+            .filter_map(|entry| {
+                let key = self.get_string(&entry, "0")?;
+                let property = match self.get_string(&entry, "1")?.as_str() {
+                    "get" => Property::Getter,
+                    "set" => Property::Setter,
+                    "getset" => Property::GetterSetter,
+                    _ => Property::Value(self.get(&entry, "2").unwrap_or_default()),
+                };
+                Some((key, property))
+            })
+            .collect()
+    }
+
+    fn array_length(&mut self, value: &JsValue) -> usize {
+        self.get(value, "length")
+            .and_then(|len| len.to_number(self.context).ok())
+            .unwrap_or_default() as usize
+    }
+
+    fn array_elements(&mut self, value: &JsValue, limit: usize) -> Vec<JsValue> {
+        let Some(array) = value.as_object() else {
+            return Vec::new();
+        };
+        let len = self.array_length(value).min(limit);
+
+        (0..len)
+            .map(|index| array.get(index, self.context).unwrap_or_default())
+            .collect()
+    }
+
+    fn collection_size(&mut self, value: &JsValue) -> usize {
+        self.get(value, "size")
+            .and_then(|size| size.to_number(self.context).ok())
+            .unwrap_or_default() as usize
+    }
+
+    fn collection_entries(
+        &mut self,
+        value: &JsValue,
+        limit: usize,
+    ) -> Vec<(Option<JsValue>, JsValue)> {
+        let is_map = matches!(self.kind(value), Kind::Map);
+        let args = [value.clone(), JsValue::new(limit as f64)];
+        let Some(array) = self.call_helper("toArray", &args) else {
+            return Vec::new();
+        };
+
+        self.array_elements(&array, usize::MAX)
+            .into_iter()
+            .map(|entry| {
+                if is_map {
+                    let key = self.get(&entry, "0");
+                    let value = self.get(&entry, "1").unwrap_or_default();
+                    (key, value)
+                } else {
+                    (None, entry)
+                }
+            })
+            .collect()
+    }
+
+    fn promise_state(&mut self, value: &JsValue) -> inspect::PromiseState<JsValue> {
+        let promise = value
+            .as_object()
+            .and_then(|o| JsPromise::from_object(o.clone()).ok());
+        match promise.map(|promise| promise.state()) {
+            Some(PromiseState::Fulfilled(value)) => inspect::PromiseState::Fulfilled(value),
+            Some(PromiseState::Rejected(reason)) => inspect::PromiseState::Rejected(reason),
+            Some(PromiseState::Pending) | None => inspect::PromiseState::Pending,
+        }
+    }
+}
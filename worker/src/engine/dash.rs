@@ -0,0 +1,608 @@
+//! The dash JavaScript engine
+
+use std::time::Duration;
+use std::time::Instant;
+
+use dash_compiler::FunctionCompiler;
+use dash_lexer::Lexer;
+use dash_middle::interner::StringInterner;
+use dash_middle::parser::error::IntoFormattableErrors;
+use dash_optimizer::OptLevel;
+use dash_parser::Parser;
+use dash_vm::eval::EvalError;
+use dash_vm::gc::persistent::Persistent;
+use dash_vm::localscope::LocalScope;
+use dash_vm::params::VmParams;
+use dash_vm::value::array::Array;
+use dash_vm::value::error::Error;
+use dash_vm::value::map::Map;
+use dash_vm::value::object::Object;
+use dash_vm::value::object::PropertyKey;
+use dash_vm::value::object::PropertyValueKind;
+use dash_vm::value::ops::conversions::ValueConversion;
+use dash_vm::value::primitive::Number;
+use dash_vm::value::promise::Promise;
+use dash_vm::value::promise::PromiseState;
+use dash_vm::value::root_ext::RootOkExt;
+use dash_vm::value::set::Set;
+use dash_vm::value::Root;
+use dash_vm::value::Typeof;
+use dash_vm::value::Value;
+use dash_vm::Vm;
+use shared::ErrorKind;
+use shared::EvalOptions;
+use shared::EvalOutput;
+use shared::EvalStats;
+use shared::Span;
+
+use crate::clock;
+use crate::inspect;
+use crate::inspect::InspectOptions;
+use crate::inspect::Kind;
+use crate::inspect::Property;
+use crate::inspect::Reflect;
+use crate::limits;
+use crate::steps;
+
+use super::drive_event_loop;
+use super::Engine;
+
+fn fmt_value(to_array: &Persistent, value: Value, options: InspectOptions, vm: &mut Vm) -> String {
+    let scope = &mut vm.scope();
+    let mut reflect = DashReflect { scope, to_array };
+    inspect::inspect(&mut reflect, &value, options)
+}
+
+pub fn shared_opt_to_dash_opt(opt: shared::Opt) -> OptLevel {
+    match opt {
+        shared::Opt::None => OptLevel::None,
+        shared::Opt::Basic => OptLevel::Basic,
+        shared::Opt::Aggressive => OptLevel::Aggressive,
+    }
+}
+
+/// Times parsing and compiling on their own.
+///
+/// `Vm::eval` doesn't report its stages separately, so they are repeated here with a throwaway
+/// interner. Returns zero for the compile time if the code doesn't parse.
+fn front_end_durations(code: &str, opt: shared::Opt) -> (Duration, Duration) {
+    let mut interner = StringInterner::new();
+
+    let start = Instant::now();
+    let parsed = match Lexer::new(&mut interner, code).scan_all() {
+        Ok(tokens) => Parser::new(&mut interner, code, tokens).parse_all().is_ok(),
+        Err(_) => false,
+    };
+    let parse = start.elapsed();
+    if !parsed {
+        return (parse, Duration::ZERO);
+    }
+
+    let start = Instant::now();
+    let _ = FunctionCompiler::compile_str(&mut interner, code, shared_opt_to_dash_opt(opt));
+    // compile_str parses the code again
+    let compile = start.elapsed().saturating_sub(parse);
+
+    (parse, compile)
+}
+
+/// Drives the event loop until the value (if it is a promise) has settled and no timers are left,
+/// or the time budget is used up. Then formats the settled value.
+fn run_event_loop(
+    to_array: &Persistent,
+    options: InspectOptions,
+    settle: &Persistent,
+    value: Value,
+    started: Instant,
+    timeout: Duration,
+    vm: &mut Vm,
+) -> Result<String, shared::EvalError> {
+    let sc = &mut vm.scope();
+    let Ok(Value::Object(poll)) = settle
+        .apply(sc, Value::undefined(), vec![value])
+        .root_ok(sc)
+    else {
+        return Err(shared::EvalError::new(
+            ErrorKind::Internal,
+            "failed to set up the event loop",
+        ));
+    };
+
+    drive_event_loop(started, timeout, || {
+        sc.process_async_tasks();
+
+        match poll.apply(sc, Value::undefined(), Vec::new()).root_ok(sc) {
+            Ok(delay) => Ok(delay.to_number(sc).unwrap_or(-1.0)),
+            // a timer callback threw
+            Err(err) => {
+                let err = err.root(sc);
+                Err(exception_error(to_array, err, options, sc))
+            }
+        }
+    })?;
+
+    sc.process_async_tasks();
+
+    match poll
+        .apply(sc, Value::undefined(), vec![Value::Boolean(true)])
+        .root_ok(sc)
+    {
+        Ok(value) => Ok(fmt_value(to_array, value, options, sc)),
+        Err(err) => {
+            let err = err.root(sc);
+            Err(exception_error(to_array, err, options, sc))
+        }
+    }
+}
+
+fn exception_error(
+    to_array: &Persistent,
+    value: Value,
+    options: InspectOptions,
+    vm: &mut Vm,
+) -> shared::EvalError {
+    shared::EvalError::new(
+        ErrorKind::Exception,
+        fmt_value(to_array, value, options, vm),
+    )
+}
+
+pub fn syntax_error(
+    code: &str,
+    errors: Vec<dash_middle::parser::error::Error>,
+) -> shared::EvalError {
+    let spans = errors
+        .iter()
+        .map(|err| {
+            let span = err.span();
+            Span {
+                lo: span.lo,
+                hi: span.hi,
+            }
+        })
+        .collect();
+
+    shared::EvalError {
+        kind: ErrorKind::Syntax,
+        message: errors.formattable(code, true).to_string(),
+        spans,
+    }
+}
+
+/// A VM together with the helper functions that were evaluated into it
+pub struct Dash {
+    vm: Vm,
+    /// Converts the first entries of a map or set to an array, used by [`DashReflect`]
+    to_array: Persistent,
+    drain_logs: Persistent,
+    settle: Persistent,
+    /// Sets the step budget, see `steps.js`
+    budget: Persistent,
+}
+
+impl Dash {
+    pub fn new() -> Self {
+        let params = VmParams::default()
+            .set_math_random_callback(|_| Ok(clock::random()))
+            // dash has no other way to interrupt a script, so once a limit was hit the clock throws.
+            // `steps.js` reads it regularly while loops are running
+            .set_time_millis_callback(|_| {
+                if limits::memory_exceeded() || limits::deadline_passed() {
+                    Err(Value::undefined().into())
+                } else {
+                    Ok(clock::now_millis())
+                }
+            });
+        let mut vm = Vm::new(params);
+        let (to_array, drain_logs, settle, budget) = {
+            const TO_ARRAY_CODE: &str = "(function (value, limit) {
+                const entries = [];
+                for (const entry of value) {
+                    if (entries.length >= limit) {
+                        break;
+                    }
+                    entries.push(entry);
+                }
+                return entries;
+            })";
+            const CHECK_LIMITS_CODE: &str = "(function () { Date.now(); })";
+            const CONSOLE_CODE: &str = include_str!("../../js/console.js");
+            const EVENT_LOOP_CODE: &str = include_str!("../../js/event_loop.js");
+            const STEPS_CODE: &str = include_str!("../../js/steps.js");
+            let scope = &mut vm.scope();
+
+            let Value::Object(to_array) = scope
+                .eval(TO_ARRAY_CODE, OptLevel::Basic)
+                .unwrap()
+                .root(scope)
+            else {
+                unreachable!()
+            };
+
+            let Value::Object(setup_console) = scope
+                .eval(CONSOLE_CODE, OptLevel::Basic)
+                .unwrap()
+                .root(scope)
+            else {
+                unreachable!()
+            };
+
+            let Ok(Value::Object(drain_logs)) = setup_console
+                .apply(scope, Value::undefined(), Vec::new())
+                .root_ok(scope)
+            else {
+                unreachable!()
+            };
+
+            let Value::Object(setup_event_loop) = scope
+                .eval(EVENT_LOOP_CODE, OptLevel::Basic)
+                .unwrap()
+                .root(scope)
+            else {
+                unreachable!()
+            };
+
+            let Ok(Value::Object(settle)) = setup_event_loop
+                .apply(scope, Value::undefined(), Vec::new())
+                .root_ok(scope)
+            else {
+                unreachable!()
+            };
+
+            let check_limits = scope
+                .eval(CHECK_LIMITS_CODE, OptLevel::Basic)
+                .unwrap()
+                .root(scope);
+            let Value::Object(setup_steps) =
+                scope.eval(STEPS_CODE, OptLevel::Basic).unwrap().root(scope)
+            else {
+                unreachable!()
+            };
+
+            let Ok(Value::Object(budget)) = setup_steps
+                .apply(scope, Value::undefined(), vec![check_limits])
+                .root_ok(scope)
+            else {
+                unreachable!()
+            };
+
+            (
+                Persistent::new(scope, to_array),
+                Persistent::new(scope, drain_logs),
+                Persistent::new(scope, settle),
+                Persistent::new(scope, budget),
+            )
+        };
+
+        Self {
+            vm,
+            to_array,
+            drain_logs,
+            settle,
+            budget,
+        }
+    }
+
+    /// Gives the next evaluation `steps` loop iterations.
+    /// Returns whether the previous evaluation ran out of them
+    fn set_step_budget(&mut self, steps: f64) -> bool {
+        let scope = &mut self.vm.scope();
+        match self
+            .budget
+            .apply(
+                scope,
+                Value::undefined(),
+                vec![Value::Number(Number(steps))],
+            )
+            .root_ok(scope)
+        {
+            Ok(Value::Boolean(ran_out)) => ran_out,
+            _ => false,
+        }
+    }
+
+    /// Returns everything that was logged to the console since the last call.
+    /// Strings are logged as they are, other values are formatted
+    fn drain_logs(&mut self, options: InspectOptions) -> String {
+        let scope = &mut self.vm.scope();
+        let calls = match self
+            .drain_logs
+            .apply(scope, Value::undefined(), Vec::new())
+            .root_ok(scope)
+        {
+            Ok(calls) => calls,
+            Err(_) => return String::new(),
+        };
+
+        let mut reflect = DashReflect {
+            scope,
+            to_array: &self.to_array,
+        };
+        inspect::format_console_calls(&mut reflect, &calls, options)
+    }
+
+    /// Evaluates the code as the body of a function and returns that function without calling it
+    pub fn eval_function(
+        &mut self,
+        code: &str,
+        opt: shared::Opt,
+    ) -> Result<Persistent, shared::EvalError> {
+        let code = format!("(function () {{\n{code}\n}})");
+        let scope = &mut self.vm.scope();
+
+        match scope.eval(&code, shared_opt_to_dash_opt(opt)) {
+            Ok(v) => match v.root(scope) {
+                Value::Object(function) => Ok(Persistent::new(scope, function)),
+                _ => unreachable!("function expression evaluated to a non-object"),
+            },
+            Err(EvalError::Exception(v)) => Err(exception_error(
+                &self.to_array,
+                v.root(scope),
+                InspectOptions::default(),
+                scope,
+            )),
+            Err(EvalError::Middle(errors)) => Err(syntax_error(&code, errors)),
+        }
+    }
+
+    /// Like [`Dash::call`], but gives the function [`steps::MAX_STEPS`] loop iterations.
+    /// Only functions that were evaluated from code rewritten by [`steps::instrument`] count them
+    pub fn call_limited(&mut self, function: &Persistent) -> Result<(), shared::EvalError> {
+        self.set_step_budget(steps::MAX_STEPS as f64);
+        let result = self.call(function);
+        if self.set_step_budget(f64::INFINITY) {
+            return Err(steps::limit_exceeded());
+        }
+        result
+    }
+
+    /// Calls a function returned by [`Dash::eval_function`], discarding its return value
+    pub fn call(&mut self, function: &Persistent) -> Result<(), shared::EvalError> {
+        let scope = &mut self.vm.scope();
+
+        match function
+            .apply(scope, Value::undefined(), Vec::new())
+            .root_ok(scope)
+        {
+            Ok(_) => Ok(()),
+            Err(v) => Err(exception_error(
+                &self.to_array,
+                v.root(scope),
+                InspectOptions::default(),
+                scope,
+            )),
+        }
+    }
+}
+
+impl Engine for Dash {
+    fn eval(&mut self, code: &str, options: EvalOptions) -> EvalOutput {
+        let _deterministic = options.seed.map(clock::deterministic);
+        let inspect_options = InspectOptions {
+            colors: options.colors,
+            ..InspectOptions::default()
+        };
+        let front_end = options
+            .stats
+            .then(|| front_end_durations(code, options.opt));
+        let heap_before = limits::allocated();
+        self.set_step_budget(steps::MAX_STEPS as f64);
+        let start = Instant::now();
+        let mut result = {
+            let scope = &mut self.vm.scope();
+            let opt = shared_opt_to_dash_opt(options.opt);
+            // syntax errors are reported for the code as it was written
+            let evaluated = match scope.eval(&steps::instrument(code), opt) {
+                Err(EvalError::Middle(_)) => scope.eval(code, opt),
+                evaluated => evaluated,
+            };
+
+            match evaluated {
+                Ok(v) => run_event_loop(
+                    &self.to_array,
+                    inspect_options,
+                    &self.settle,
+                    v.root(scope),
+                    start,
+                    options.timeout,
+                    scope,
+                ),
+                Err(EvalError::Exception(v)) => Err(exception_error(
+                    &self.to_array,
+                    v.root(scope),
+                    inspect_options,
+                    scope,
+                )),
+                Err(EvalError::Middle(errors)) => Err(syntax_error(code, errors)),
+            }
+        };
+        let duration = start.elapsed();
+        if self.set_step_budget(f64::INFINITY) {
+            result = Err(steps::limit_exceeded());
+        }
+        let stats = front_end.map(|(parse, compile)| EvalStats {
+            parse,
+            compile,
+            execute: duration.saturating_sub(parse + compile),
+            // dash doesn't count its garbage collections
+            gc_cycles: None,
+            heap_before,
+            heap_after: limits::allocated(),
+        });
+
+        EvalOutput {
+            result,
+            logs: self.drain_logs(inspect_options),
+            duration,
+            stats,
+        }
+    }
+}
+
+/// [`Reflect`] for dash values.
+///
+/// Map and set entries are read through `to_array`, a JS function that iterates over them and
+/// collects the first few into an array, to not depend on the internal layout of those objects.
+pub struct DashReflect<'a, 'vm> {
+    pub scope: &'a mut LocalScope<'vm>,
+    pub to_array: &'a Persistent,
+}
+
+impl DashReflect<'_, '_> {
+    fn get(&mut self, value: &Value, key: &str) -> Option<Value> {
+        let key = PropertyKey::from(self.scope.intern(key));
+        value.get_property(self.scope, key).root(self.scope).ok()
+    }
+
+    fn get_string(&mut self, value: &Value, key: &str) -> Option<String> {
+        let value = self.get(value, key)?;
+        let string = value.to_js_string(self.scope).ok()?;
+        Some(string.res(self.scope).to_owned())
+    }
+}
+
+impl Reflect for DashReflect<'_, '_> {
+    type Value = Value;
+
+    fn kind(&mut self, value: &Value) -> Kind {
+        match value {
+            Value::Undefined(_) => Kind::Undefined,
+            Value::Null(_) => Kind::Null,
+            Value::Boolean(b) => Kind::Boolean(*b),
+            Value::Number(Number(n)) => Kind::Number(*n),
+            Value::String(s) => Kind::String(s.res(self.scope).to_owned()),
+            Value::Symbol(s) => Kind::Symbol(s.description().res(self.scope).to_owned()),
+            Value::Object(o) | Value::External(o) => {
+                let any = o.as_any();
+                if o.type_of() == Typeof::Function {
+                    Kind::Function(self.get_string(value, "name").unwrap_or_default())
+                } else if any.downcast_ref::<Error>().is_some() {
+                    Kind::Error(self.get_string(value, "stack").unwrap_or_default())
+                } else if any.downcast_ref::<Array>().is_some() {
+                    Kind::Array
+                } else if any.downcast_ref::<Map>().is_some() {
+                    Kind::Map
+                } else if any.downcast_ref::<Set>().is_some() {
+                    Kind::Set
+                } else if any.downcast_ref::<Promise>().is_some() {
+                    Kind::Promise
+                } else {
+                    Kind::Object
+                }
+            }
+        }
+    }
+
+    fn identity(&mut self, value: &Value) -> usize {
+        match value {
+            Value::Object(o) | Value::External(o) => {
+                &**o as *const dyn Object as *const () as usize
+            }
+            _ => 0,
+        }
+    }
+
+    fn class_name(&mut self, value: &Value) -> Option<String> {
+        let constructor = self.get(value, "constructor")?;
+        let name = self.get_string(&constructor, "name")?;
+        (name != "Object" && !name.is_empty()).then_some(name)
+    }
+
+    fn properties(&mut self, value: &Value) -> Vec<(String, Property<Value>)> {
+        let (Value::Object(o) | Value::External(o)) = value else {
+            return Vec::new();
+        };
+        let Ok(keys) = o.own_keys(self.scope) else {
+            return Vec::new();
+        };
+
+        let mut properties = Vec::new();
+        for key in keys {
+            // symbol keys are not shown
+            let Value::String(key) = key else { continue };
+            let name = key.res(self.scope).to_owned();
+
+            let descriptor = o.get_property_descriptor(self.scope, PropertyKey::from(key));
+            let property = match descriptor {
+                Ok(Some(descriptor)) => match descriptor.kind {
+                    PropertyValueKind::Trap {
+                        get: Some(_),
+                        set: Some(_),
+                    } => Property::GetterSetter,
+                    PropertyValueKind::Trap { get: Some(_), .. } => Property::Getter,
+                    PropertyValueKind::Trap { .. } => Property::Setter,
+                    PropertyValueKind::Static(value) => Property::Value(value),
+                },
+                _ => continue,
+            };
+
+            properties.push((name, property));
+        }
+        properties
+    }
+
+    fn array_length(&mut self, value: &Value) -> usize {
+        self.get(value, "length")
+            .and_then(|len| len.to_number(self.scope).ok())
+            .unwrap_or_default() as usize
+    }
+
+    fn array_elements(&mut self, value: &Value, limit: usize) -> Vec<Value> {
+        let len = self.array_length(value).min(limit);
+
+        (0..len)
+            .map(|index| {
+                self.get(value, &index.to_string())
+                    .unwrap_or_else(Value::undefined)
+            })
+            .collect()
+    }
+
+    fn collection_size(&mut self, value: &Value) -> usize {
+        self.get(value, "size")
+            .and_then(|size| size.to_number(self.scope).ok())
+            .unwrap_or_default() as usize
+    }
+
+    fn collection_entries(&mut self, value: &Value, limit: usize) -> Vec<(Option<Value>, Value)> {
+        let is_map = matches!(self.kind(value), Kind::Map);
+        let limit = Value::Number(Number(limit as f64));
+        let array = match self
+            .to_array
+            .apply(self.scope, Value::undefined(), vec![value.clone(), limit])
+            .root_ok(self.scope)
+        {
+            Ok(array) => array,
+            Err(_) => return Vec::new(),
+        };
+
+        self.array_elements(&array, usize::MAX)
+            .into_iter()
+            .map(|entry| {
+                if is_map {
+                    let key = self.get(&entry, "0");
+                    let value = self.get(&entry, "1").unwrap_or_else(Value::undefined);
+                    (key, value)
+                } else {
+                    (None, entry)
+                }
+            })
+            .collect()
+    }
+
+    fn promise_state(&mut self, value: &Value) -> inspect::PromiseState<Value> {
+        let (Value::Object(o) | Value::External(o)) = value else {
+            return inspect::PromiseState::Pending;
+        };
+        let Some(promise) = o.as_any().downcast_ref::<Promise>() else {
+            return inspect::PromiseState::Pending;
+        };
+
+        let state = promise.state().borrow();
+        match &*state {
+            PromiseState::Pending { .. } => inspect::PromiseState::Pending,
+            PromiseState::Resolved(value) => inspect::PromiseState::Fulfilled(value.clone()),
+            PromiseState::Rejected(reason) => inspect::PromiseState::Rejected(reason.clone()),
+        }
+    }
+}
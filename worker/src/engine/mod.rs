@@ -0,0 +1,79 @@
+//! Script engines that the worker can evaluate code with.
+//!
+//! Every engine runs behind the same protocol, so a new language only needs an [`Engine`]
+//! implementation here and an [`EngineKind`] variant, and gets the pooling, timeouts and restarts
+//! of the bot's worker sets for free.
+
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use shared::EngineKind;
+use shared::EvalOptions;
+use shared::EvalOutput;
+
+use crate::clock;
+use crate::limits;
+
+pub mod boa;
+pub mod dash;
+pub mod rhai;
+
+/// Part of the host's timeout that is left for formatting the result and sending it back
+/// once the event loop stopped
+const RESPONSE_MARGIN: Duration = Duration::from_millis(500);
+
+pub trait Engine {
+    /// Evaluates the code. Global state is kept for the next call, which is how sessions work
+    fn eval(&mut self, code: &str, options: EvalOptions) -> EvalOutput;
+}
+
+pub fn new(kind: EngineKind) -> Box<dyn Engine> {
+    match kind {
+        EngineKind::Dash => Box::new(dash::Dash::new()),
+        EngineKind::Rhai => Box::new(rhai::Rhai::new()),
+        EngineKind::Boa => Box::new(boa::Boa::new()),
+    }
+}
+
+/// Calls `poll` until it returns a negative delay, the next timer is due after the host's
+/// `timeout` (counted from when the evaluation `started`) or the heap limit was exceeded.
+/// `poll` runs pending jobs and due timers,
+/// and returns how many milliseconds to wait for the next timer.
+///
+/// With a virtual clock, time skips ahead to the next timer instead of sleeping,
+/// and the timeout is measured in virtual time, so that it doesn't depend on how fast the code runs.
+/// Wall time is still limited by the timeout.
+pub fn drive_event_loop<E>(
+    started: Instant,
+    timeout: Duration,
+    mut poll: impl FnMut() -> Result<f64, E>,
+) -> Result<(), E> {
+    let deadline = started + timeout.saturating_sub(RESPONSE_MARGIN);
+    let mut virtual_remaining = timeout;
+    loop {
+        let delay = poll()?;
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if delay < 0.0 || remaining.is_zero() || limits::memory_exceeded() {
+            return Ok(());
+        }
+
+        let delay = Duration::from_secs_f64(delay / 1000.0);
+        if clock::is_deterministic() {
+            if delay > virtual_remaining {
+                return Ok(());
+            }
+
+            virtual_remaining -= delay;
+            clock::advance(delay.as_secs_f64() * 1000.0);
+        } else {
+            // the timer couldn't run before the deadline anyway
+            if delay > remaining {
+                return Ok(());
+            }
+
+            thread::sleep(delay);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! The Rhai scripting language
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Instant;
+
+use ::rhai::module_resolvers::DummyModuleResolver;
+use ::rhai::Dynamic;
+use ::rhai::EvalAltResult;
+use ::rhai::Scope;
+use shared::ErrorKind;
+use shared::EvalError;
+use shared::EvalOptions;
+use shared::EvalOutput;
+
+use crate::limits;
+
+use super::Engine;
+
+/// Rhai counts operations itself, so its loops don't need [`steps`](crate::steps)
+const MAX_OPERATIONS: u64 = 50_000_000;
+const MAX_CALL_LEVELS: usize = 64;
+const MAX_STRING_SIZE: usize = 1024 * 1024;
+const MAX_COLLECTION_SIZE: usize = 100_000;
+
+pub struct Rhai {
+    engine: ::rhai::Engine,
+    scope: Scope<'static>,
+    /// Everything printed by `print` and `debug` since the last evaluation
+    logs: Rc<RefCell<String>>,
+}
+
+impl Rhai {
+    pub fn new() -> Self {
+        let logs = Rc::new(RefCell::new(String::new()));
+        let mut engine = ::rhai::Engine::new();
+
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(MAX_CALL_LEVELS)
+            .set_max_string_size(MAX_STRING_SIZE)
+            .set_max_array_size(MAX_COLLECTION_SIZE)
+            .set_max_map_size(MAX_COLLECTION_SIZE)
+            // the default resolver would load scripts from the file system
+            .set_module_resolver(DummyModuleResolver::new());
+
+        // stops the script once it went over the heap limit, which `run_limited` then reports
+        engine.on_progress(|_| {
+            limits::memory_exceeded().then(|| Dynamic::from("memory limit exceeded"))
+        });
+
+        let print_logs = logs.clone();
+        engine.on_print(move |text| {
+            let mut logs = print_logs.borrow_mut();
+            logs.push_str(text);
+            logs.push('\n');
+        });
+
+        let debug_logs = logs.clone();
+        engine.on_debug(move |text, _, _| {
+            let mut logs = debug_logs.borrow_mut();
+            logs.push_str(text);
+            logs.push('\n');
+        });
+
+        Self {
+            engine,
+            scope: Scope::new(),
+            logs,
+        }
+    }
+}
+
+impl Engine for Rhai {
+    fn eval(&mut self, code: &str, _: EvalOptions) -> EvalOutput {
+        let start = Instant::now();
+        let result = self
+            .engine
+            .eval_with_scope::<Dynamic>(&mut self.scope, code);
+        let duration = start.elapsed();
+
+        let result = match result {
+            Ok(value) => Ok(format!("{value:?}")),
+            Err(err) => {
+                let kind = match &*err {
+                    EvalAltResult::ErrorParsing(..) => ErrorKind::Syntax,
+                    EvalAltResult::ErrorTooManyOperations(..)
+                    | EvalAltResult::ErrorStackOverflow(..)
+                    | EvalAltResult::ErrorDataTooLarge(..)
+                    | EvalAltResult::ErrorTerminated(..) => ErrorKind::LimitExceeded,
+                    _ => ErrorKind::Exception,
+                };
+                Err(EvalError::new(kind, err.to_string()))
+            }
+        };
+
+        EvalOutput {
+            result,
+            logs: std::mem::take(&mut *self.logs.borrow_mut()),
+            duration,
+            stats: None,
+        }
+    }
+}
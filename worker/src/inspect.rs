@@ -0,0 +1,439 @@
+//! Formats JavaScript values for display, similar to node's `util.inspect`.
+//!
+//! The formatter itself only needs a few primitive operations on values,
+//! which engines provide by implementing [`Reflect`].
+
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy)]
+pub struct InspectOptions {
+    /// Objects nested deeper than this are shown as `[Object]`
+    pub max_depth: usize,
+    /// Arrays, objects, maps and sets show at most this many entries
+    pub max_width: usize,
+    /// Colorize the output with ANSI escape codes
+    pub colors: bool,
+}
+
+impl Default for InspectOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 4,
+            max_width: 50,
+            colors: false,
+        }
+    }
+}
+
+/// What kind of value something is, as far as formatting is concerned
+pub enum Kind {
+    Undefined,
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    /// The digits of the bigint
+    BigInt(String),
+    /// The description of the symbol
+    Symbol(String),
+    /// The name of the function
+    Function(String),
+    /// The `stack` property of the error
+    Error(String),
+    Array,
+    Map,
+    Set,
+    Promise,
+    Object,
+}
+
+pub enum Property<V> {
+    Value(V),
+    Getter,
+    Setter,
+    GetterSetter,
+}
+
+pub enum PromiseState<V> {
+    Pending,
+    Fulfilled(V),
+    Rejected(V),
+}
+
+/// Primitive operations on values of an engine
+pub trait Reflect {
+    type Value;
+
+    fn kind(&mut self, value: &Self::Value) -> Kind;
+    /// An ID that is equal for the same object, used for cycle detection
+    fn identity(&mut self, value: &Self::Value) -> usize;
+    /// Name of the constructor, if it is not `Object`
+    fn class_name(&mut self, value: &Self::Value) -> Option<String>;
+    /// Own enumerable string keys and their values
+    fn properties(&mut self, value: &Self::Value) -> Vec<(String, Property<Self::Value>)>;
+    fn array_length(&mut self, value: &Self::Value) -> usize;
+    /// The first `limit` elements of an array, so that huge arrays aren't read completely
+    fn array_elements(&mut self, value: &Self::Value, limit: usize) -> Vec<Self::Value>;
+    /// Number of entries of a map or set
+    fn collection_size(&mut self, value: &Self::Value) -> usize;
+    /// The first `limit` entries of a map or values of a set
+    fn collection_entries(
+        &mut self,
+        value: &Self::Value,
+        limit: usize,
+    ) -> Vec<(Option<Self::Value>, Self::Value)>;
+    fn promise_state(&mut self, value: &Self::Value) -> PromiseState<Self::Value>;
+}
+
+mod color {
+    pub const RESET: &str = "\x1b[0m";
+    pub const BOLD: &str = "\x1b[1m";
+    pub const GREEN: &str = "\x1b[32m";
+    pub const YELLOW: &str = "\x1b[33m";
+    pub const CYAN: &str = "\x1b[36m";
+    pub const GRAY: &str = "\x1b[90m";
+}
+
+struct Inspector<'r, R> {
+    reflect: &'r mut R,
+    options: InspectOptions,
+    /// Identities of the objects that are currently being formatted
+    stack: Vec<usize>,
+    out: String,
+}
+
+impl<R: Reflect> Inspector<'_, R> {
+    fn colored(&mut self, color: &str, text: &str) {
+        if self.options.colors {
+            self.out.push_str(color);
+            self.out.push_str(text);
+            self.out.push_str(color::RESET);
+        } else {
+            self.out.push_str(text);
+        }
+    }
+
+    fn write_value(&mut self, value: &R::Value, depth: usize) {
+        match self.reflect.kind(value) {
+            Kind::Undefined => self.colored(color::GRAY, "undefined"),
+            Kind::Null => self.colored(color::BOLD, "null"),
+            Kind::Boolean(b) => self.colored(color::YELLOW, &b.to_string()),
+            Kind::Number(n) => self.colored(color::YELLOW, &format_number(n)),
+            Kind::String(s) => self.colored(color::GREEN, &quote_string(&s)),
+            Kind::BigInt(digits) => self.colored(color::YELLOW, &format!("{digits}n")),
+            Kind::Symbol(description) => {
+                self.colored(color::GREEN, &format!("Symbol({description})"))
+            }
+            Kind::Function(name) if name.is_empty() => {
+                self.colored(color::CYAN, "[Function: (anonymous)]")
+            }
+            Kind::Function(name) => self.colored(color::CYAN, &format!("[Function: {name}]")),
+            Kind::Error(stack) => self.out.push_str(&stack),
+            kind => self.write_object(value, kind, depth),
+        }
+    }
+
+    fn write_object(&mut self, value: &R::Value, kind: Kind, depth: usize) {
+        let identity = self.reflect.identity(value);
+        if self.stack.contains(&identity) {
+            self.colored(color::CYAN, "[Circular]");
+            return;
+        }
+
+        let class_name = self.reflect.class_name(value);
+        if depth > self.options.max_depth {
+            let name = match kind {
+                Kind::Array => "Array",
+                _ => class_name.as_deref().unwrap_or("Object"),
+            };
+            self.colored(color::CYAN, &format!("[{name}]"));
+            return;
+        }
+
+        self.stack.push(identity);
+        match kind {
+            Kind::Array => {
+                let len = self.reflect.array_length(value);
+                let elements = self.reflect.array_elements(value, self.options.max_width);
+                if let Some(name) = class_name.filter(|name| name != "Array") {
+                    let _ = write!(self.out, "{name}({len}) ");
+                }
+                self.write_list("[", "]", len, elements, |this, element| {
+                    this.write_value(&element, depth + 1)
+                });
+            }
+            Kind::Map | Kind::Set => {
+                let size = self.reflect.collection_size(value);
+                let entries = self
+                    .reflect
+                    .collection_entries(value, self.options.max_width);
+                let name = match kind {
+                    Kind::Map => "Map",
+                    _ => "Set",
+                };
+                let _ = write!(
+                    self.out,
+                    "{}({size}) ",
+                    class_name.as_deref().unwrap_or(name)
+                );
+                self.write_list("{", "}", size, entries, |this, (key, value)| {
+                    if let Some(key) = key {
+                        this.write_value(&key, depth + 1);
+                        this.out.push_str(" => ");
+                    }
+                    this.write_value(&value, depth + 1);
+                });
+            }
+            Kind::Promise => {
+                self.out.push_str("Promise { ");
+                match self.reflect.promise_state(value) {
+                    PromiseState::Pending => self.colored(color::CYAN, "<pending>"),
+                    PromiseState::Fulfilled(value) => self.write_value(&value, depth + 1),
+                    PromiseState::Rejected(reason) => {
+                        self.colored(color::CYAN, "<rejected>");
+                        self.out.push(' ');
+                        self.write_value(&reason, depth + 1);
+                    }
+                }
+                self.out.push_str(" }");
+            }
+            _ => {
+                let properties = self.reflect.properties(value);
+                if let Some(name) = class_name {
+                    self.out.push_str(&name);
+                    self.out.push(' ');
+                }
+                self.write_list(
+                    "{",
+                    "}",
+                    properties.len(),
+                    properties,
+                    |this, (key, property)| {
+                        this.out.push_str(&key);
+                        this.out.push_str(": ");
+                        match property {
+                            Property::Value(value) => this.write_value(&value, depth + 1),
+                            Property::Getter => this.colored(color::CYAN, "[Getter]"),
+                            Property::Setter => this.colored(color::CYAN, "[Setter]"),
+                            Property::GetterSetter => this.colored(color::CYAN, "[Getter/Setter]"),
+                        }
+                    },
+                );
+            }
+        }
+        self.stack.pop();
+    }
+
+    /// Writes the items, separated by commas and enclosed in `open` and `close`,
+    /// stopping after `max_width` items. `len` is the number of items there are in total
+    fn write_list<T>(
+        &mut self,
+        open: &str,
+        close: &str,
+        len: usize,
+        items: Vec<T>,
+        mut write_item: impl FnMut(&mut Self, T),
+    ) {
+        if len == 0 {
+            self.out.push_str(open);
+            self.out.push_str(close);
+            return;
+        }
+
+        // node puts spaces inside of braces, but not inside of brackets
+        let padding = if open == "[" { "" } else { " " };
+        self.out.push_str(open);
+        self.out.push_str(padding);
+
+        for (index, item) in items.into_iter().take(self.options.max_width).enumerate() {
+            if index > 0 {
+                self.out.push_str(", ");
+            }
+            write_item(self, item);
+        }
+
+        if len > self.options.max_width {
+            let _ = write!(
+                self.out,
+                ", ... {} more items",
+                len - self.options.max_width
+            );
+        }
+
+        self.out.push_str(padding);
+        self.out.push_str(close);
+    }
+}
+
+/// Formats a number like `Number.prototype.toString` does, except for showing `-0`
+fn format_number(n: f64) -> String {
+    if n.is_nan() {
+        return "NaN".into();
+    } else if n == 0.0 {
+        return if n.is_sign_negative() { "-0" } else { "0" }.into();
+    } else if n.is_infinite() {
+        return if n > 0.0 { "Infinity" } else { "-Infinity" }.into();
+    }
+
+    // the shortest digits that round-trip, which is what JS uses too
+    let scientific = format!("{:e}", n.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let digits = mantissa.replace('.', "");
+    let k = digits.len() as i32;
+    // position of the decimal point relative to the start of the digits
+    let point = exponent.parse::<i32>().unwrap_or_default() + 1;
+
+    let mut out = String::new();
+    if n < 0.0 {
+        out.push('-');
+    }
+    if k <= point && point <= 21 {
+        out.push_str(&digits);
+        out.extend(std::iter::repeat('0').take((point - k) as usize));
+    } else if 0 < point && point <= 21 {
+        out.push_str(&digits[..point as usize]);
+        out.push('.');
+        out.push_str(&digits[point as usize..]);
+    } else if -6 < point && point <= 0 {
+        out.push_str("0.");
+        out.extend(std::iter::repeat('0').take(-point as usize));
+        out.push_str(&digits);
+    } else {
+        out.push_str(&digits[..1]);
+        if k > 1 {
+            out.push('.');
+            out.push_str(&digits[1..]);
+        }
+        let sign = if point > 0 { '+' } else { '-' };
+        let _ = write!(out, "e{sign}{}", (point - 1).abs());
+    }
+    out
+}
+
+/// Quotes a string like node does, escaping backslashes and control characters.
+/// Single quotes are used unless the string contains some, then double quotes or backticks
+/// are used if that avoids escaping
+fn quote_string(s: &str) -> String {
+    let quote = if !s.contains('\'') {
+        '\''
+    } else if !s.contains('"') {
+        '"'
+    } else if !s.contains('`') && !s.contains("${") {
+        '`'
+    } else {
+        '\''
+    };
+
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push(quote);
+    for c in s.chars() {
+        match c {
+            c if c == quote => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\x{:02X}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push(quote);
+    out
+}
+
+pub fn inspect<R: Reflect>(reflect: &mut R, value: &R::Value, options: InspectOptions) -> String {
+    let mut inspector = Inspector {
+        reflect,
+        options,
+        stack: Vec::new(),
+        out: String::new(),
+    };
+    inspector.write_value(value, 0);
+    inspector.out
+}
+
+/// Formats the arguments of console calls like `console.log` does, one call per line.
+/// `calls` is an array of arrays of arguments. Strings are written as they are, without quotes
+pub fn format_console_calls<R: Reflect>(
+    reflect: &mut R,
+    calls: &R::Value,
+    options: InspectOptions,
+) -> String {
+    let mut logs = String::new();
+    for args in reflect.array_elements(calls, usize::MAX) {
+        for (index, arg) in reflect
+            .array_elements(&args, usize::MAX)
+            .into_iter()
+            .enumerate()
+        {
+            if index > 0 {
+                logs.push(' ');
+            }
+
+            match reflect.kind(&arg) {
+                Kind::String(s) => logs.push_str(&s),
+                _ => logs.push_str(&inspect(reflect, &arg, options)),
+            }
+        }
+        logs.push('\n');
+    }
+    logs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_number;
+    use super::quote_string;
+
+    #[test]
+    fn numbers() {
+        for (n, expected) in [
+            (0.0, "0"),
+            (-0.0, "-0"),
+            (f64::NAN, "NaN"),
+            (f64::INFINITY, "Infinity"),
+            (f64::NEG_INFINITY, "-Infinity"),
+            (1.0, "1"),
+            (-42.0, "-42"),
+            (123.456, "123.456"),
+            (0.1, "0.1"),
+            (0.1 + 0.2, "0.30000000000000004"),
+            (1e20, "100000000000000000000"),
+            (1e21, "1e+21"),
+            (1.5e300, "1.5e+300"),
+            (0.000001, "0.000001"),
+            (1e-7, "1e-7"),
+            (-1.25e-10, "-1.25e-10"),
+            (f64::MAX, "1.7976931348623157e+308"),
+            (5e-324, "5e-324"),
+        ] {
+            assert_eq!(format_number(n), expected, "{n:?}");
+        }
+    }
+
+    #[test]
+    fn strings() {
+        for (s, expected) in [
+            ("", "''"),
+            ("abc", "'abc'"),
+            ("it's", r#""it's""#),
+            (r#"it's "quoted""#, r#"`it's "quoted"`"#),
+            (r#"it's "quoted" `twice`"#, r#"'it\'s "quoted" `twice`'"#),
+            (r#"it's "${x}""#, r#"'it\'s "${x}"'"#),
+            ("a\\b", r"'a\\b'"),
+            ("line\nbreak\ttab\r", r"'line\nbreak\ttab\r'"),
+            ("\u{8}\u{b}\u{c}", r"'\b\x0B\f'"),
+            ("\0\u{1b}[0m\u{7f}\u{85}", r"'\x00\x1B[0m\x7F\x85'"),
+            ("ünïcödé 🦀", "'ünïcödé 🦀'"),
+        ] {
+            assert_eq!(quote_string(s), expected, "{s:?}");
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! Per-evaluation resource limits.
+//!
+//! Heap usage is tracked per thread by a global allocator, so only the allocations of the thread
+//! running the evaluation count against its limit. Going over the limit doesn't fail the
+//! allocation, because the engines can't recover from that. Instead, [`memory_exceeded`] starts
+//! returning `true`, which the engines check at safe points (see [`steps`](crate::steps)) to throw
+//! an exception, and [`run_limited`] turns the result into a [`LimitExceeded`] error.
+//!
+//! Native code like `"x".repeat(1e9)` can allocate a lot before it gets to a check, so a single
+//! allocation is served no matter how far it goes over the limit, as long as the system has the
+//! memory. Only allocations made while the thread is already beyond [`MAX_HARD_HEAP_SIZE`] fail.
+//! Rust can't recover from a failed allocation either, so that, or the system running out of
+//! memory (e.g. at the sandbox's address space limit), still aborts the process.
+
+use std::alloc::GlobalAlloc;
+use std::alloc::Layout;
+use std::alloc::System;
+use std::cell::Cell;
+use std::fmt;
+use std::time::Instant;
+
+/// How much memory a single evaluation may allocate on top of what was allocated before it started
+pub const MAX_HEAP_SIZE: usize = 64 * 1024 * 1024;
+/// Allocations fail once this was exceeded, which aborts the process. Leaves room for the code
+/// that is running when [`MAX_HEAP_SIZE`] is reached to get to the next check, even if it is
+/// native code that grows a buffer a few times, like `Array.prototype.fill`
+const MAX_HARD_HEAP_SIZE: usize = 16 * MAX_HEAP_SIZE;
+
+thread_local! {
+    /// Bytes allocated by this thread, minus the ones it freed.
+    /// Can be negative if memory that another thread allocated is freed here
+    static ALLOCATED: Cell<isize> = const { Cell::new(0) };
+    static SOFT_LIMIT: Cell<isize> = const { Cell::new(isize::MAX) };
+    static HARD_LIMIT: Cell<isize> = const { Cell::new(isize::MAX) };
+    /// Whether [`SOFT_LIMIT`] was reached, even if the memory was freed again since
+    static EXCEEDED: Cell<bool> = const { Cell::new(false) };
+    /// When the job running on this thread has to stop, see [`run_with_deadline`]
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+pub struct LimitingAllocator;
+
+unsafe impl GlobalAlloc for LimitingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let before = ALLOCATED.get();
+        if before > HARD_LIMIT.get() {
+            return std::ptr::null_mut();
+        }
+
+        let allocated = before.saturating_add(layout.size() as isize);
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            ALLOCATED.set(allocated);
+            if allocated > SOFT_LIMIT.get() {
+                EXCEEDED.set(true);
+            }
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.set(ALLOCATED.get().saturating_sub(layout.size() as isize));
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[derive(Debug)]
+pub enum LimitExceeded {
+    Memory,
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitExceeded::Memory => f.write_str("memory limit exceeded"),
+        }
+    }
+}
+
+/// Number of bytes that are currently allocated by this thread
+pub fn allocated() -> usize {
+    ALLOCATED.get().max(0) as usize
+}
+
+/// Whether the evaluation running on this thread went over its heap limit
+pub fn memory_exceeded() -> bool {
+    EXCEEDED.get()
+}
+
+/// Runs `f` with a heap limit of `max_heap` bytes.
+///
+/// `f` is run to completion even if the limit is exceeded, so the engine has to check
+/// [`memory_exceeded`] and stop on its own. Its result is discarded in that case.
+pub fn run_limited<R>(max_heap: usize, f: impl FnOnce() -> R) -> Result<R, LimitExceeded> {
+    let start = ALLOCATED.get();
+    let max_hard_heap = max_heap.saturating_mul(MAX_HARD_HEAP_SIZE / MAX_HEAP_SIZE);
+    SOFT_LIMIT.set(start.saturating_add(max_heap as isize));
+    HARD_LIMIT.set(start.saturating_add(max_hard_heap as isize));
+    EXCEEDED.set(false);
+
+    let value = f();
+    let exceeded = EXCEEDED.replace(false);
+    SOFT_LIMIT.set(isize::MAX);
+    HARD_LIMIT.set(isize::MAX);
+
+    if exceeded {
+        Err(LimitExceeded::Memory)
+    } else {
+        Ok(value)
+    }
+}
+
+/// Restores the previous deadline when dropped, even if `f` panicked
+struct RestoreDeadline(Option<Instant>);
+
+impl Drop for RestoreDeadline {
+    fn drop(&mut self) {
+        DEADLINE.set(self.0);
+    }
+}
+
+/// Runs `f` with a deadline, after which [`deadline_passed`] returns `true`.
+///
+/// Like with the heap limit, the engine has to check it and stop on its own.
+/// Only dash does, while loops run (see [`steps`](crate::steps)) and whenever `Date.now` is called
+pub fn run_with_deadline<R>(deadline: Instant, f: impl FnOnce() -> R) -> R {
+    let _restore = RestoreDeadline(DEADLINE.replace(Some(deadline)));
+    f()
+}
+
+/// Whether the deadline of the job running on this thread passed
+pub fn deadline_passed() -> bool {
+    DEADLINE
+        .get()
+        .is_some_and(|deadline| Instant::now() >= deadline)
+}
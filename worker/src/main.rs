@@ -1,117 +1,287 @@
-use std::time::SystemTime;
-
-use anyhow::anyhow;
-use anyhow::bail;
-use dash_compiler::FunctionCompiler;
-use dash_decompiler::decompile;
-use dash_middle::interner::StringInterner;
-use dash_middle::parser::error::IntoFormattableErrors;
-use dash_optimizer::OptLevel;
-use dash_vm::eval::EvalError;
-use dash_vm::gc::persistent::Persistent;
-use dash_vm::params::VmParams;
-use dash_vm::value::ops::conversions::ValueConversion;
-use dash_vm::value::root_ext::RootOkExt;
-use dash_vm::value::Root;
-use dash_vm::value::Value;
-use dash_vm::Vm;
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
+
+use engine::Engine;
 use ipc2_worker::Job;
 use shared::ClientMessage;
+use shared::EngineKind;
+use shared::ErrorKind;
+use shared::EvalOutput;
+use shared::Feature;
 use shared::HostMessage;
+use shared::SessionId;
+use shared::WorkerInfo;
 use tokio::net::UnixStream;
 
-fn fmt_value(inspect: &Persistent, value: Value, vm: &mut Vm) -> anyhow::Result<String> {
-    let sc = &mut vm.scope();
-    let result = match inspect
-        .apply(sc, Value::undefined(), vec![value])
-        .root_ok(sc)
-    {
-        Ok(v) => v,
-        Err(_) => bail!("inspect function threw an exception"),
-    };
-
-    match result.to_js_string(sc) {
-        Ok(v) => Ok(v.res(sc).to_owned()),
-        Err(_) => Err(anyhow!("failed to convert inspected value to a string")),
+mod bench;
+mod clock;
+mod crash;
+mod dump;
+mod engine;
+mod inspect;
+mod limits;
+mod sandbox;
+mod steps;
+mod wasm;
+
+#[global_allocator]
+static ALLOCATOR: limits::LimitingAllocator = limits::LimitingAllocator;
+
+/// Sessions that haven't been used for this long are dropped
+const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+/// Maximum number of sessions kept alive at once. The least recently used session is dropped first
+const MAX_SESSIONS: usize = 64;
+/// CPU time a single job may use before the process is killed.
+/// Well above the host's timeouts, this only matters if the host fails to restart the worker
+const JOB_CPU_TIME: Duration = Duration::from_secs(60);
+
+fn limit_exceeded_output(err: limits::LimitExceeded) -> EvalOutput {
+    EvalOutput {
+        result: Err(shared::EvalError::new(
+            ErrorKind::LimitExceeded,
+            err.to_string(),
+        )),
+        logs: String::new(),
+        duration: Duration::ZERO,
+        stats: None,
     }
 }
 
-fn shared_opt_to_dash_opt(opt: shared::Opt) -> OptLevel {
-    match opt {
-        shared::Opt::None => OptLevel::None,
-        shared::Opt::Basic => OptLevel::Basic,
-        shared::Opt::Aggressive => OptLevel::Aggressive,
+fn crash_output(report: String) -> EvalOutput {
+    EvalOutput {
+        result: Err(shared::EvalError::new(ErrorKind::Crash, report)),
+        logs: String::new(),
+        duration: Duration::ZERO,
+        stats: None,
     }
 }
 
-#[tokio::main]
+/// Runs a job that doesn't evaluate any code and wraps its result in an [`EvalOutput`]
+fn timed(f: impl FnOnce() -> Result<String, shared::EvalError>) -> EvalOutput {
+    let start = Instant::now();
+    let result = crash::catch(f)
+        .unwrap_or_else(|report| Err(shared::EvalError::new(ErrorKind::Crash, report)));
+
+    EvalOutput {
+        result,
+        logs: String::new(),
+        duration: start.elapsed(),
+        stats: None,
+    }
+}
+
+struct Session {
+    /// Sessions are only supported for JavaScript so far, so this is always dash
+    engine: Box<dyn Engine>,
+    last_used: Instant,
+}
+
+/// Long-lived sessions, keyed by the session ID chosen by the bot
+#[derive(Default)]
+struct Sessions(HashMap<SessionId, Session>);
+
+impl Sessions {
+    fn expire_idle(&mut self) {
+        self.0
+            .retain(|_, session| session.last_used.elapsed() < SESSION_IDLE_TIMEOUT);
+    }
+
+    /// Returns the session and whether it was just created
+    fn get_or_create(&mut self, id: SessionId) -> (&mut Session, bool) {
+        let new = !self.0.contains_key(&id);
+        if new && self.0.len() >= MAX_SESSIONS {
+            let lru = self
+                .0
+                .iter()
+                .min_by_key(|(_, session)| session.last_used)
+                .map(|(&id, _)| id);
+
+            if let Some(lru) = lru {
+                self.0.remove(&lru);
+            }
+        }
+
+        let session = self.0.entry(id).or_insert_with(|| Session {
+            engine: engine::new(EngineKind::Dash),
+            last_used: Instant::now(),
+        });
+        session.last_used = Instant::now();
+        (session, new)
+    }
+
+    fn reset(&mut self, id: SessionId) {
+        self.0.remove(&id);
+    }
+}
+
+/// The runtime runs on the main thread only. The sandbox forbids starting threads, so a runtime
+/// that starts them on demand (like the multi-threaded one does for blocking tasks) would fail
+/// once the sandbox is entered, and the heap limit is only tracked for the thread running the job
+#[tokio::main(flavor = "current_thread")]
 async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
+    crash::install();
     let mut rx = ipc2_worker::connect::<UnixStream, HostMessage, ClientMessage>().await?;
+    sandbox::enter()?;
+    let mut sessions = Sessions::default();
 
     while let Some(job) = rx.recv().await {
+        sessions.expire_idle();
+        if let Err(err) = sandbox::limit_cpu_time(JOB_CPU_TIME) {
+            tracing::error!(%err, "failed to limit the CPU time of the job");
+        }
+
         match job {
             Job::Bidirectional { data, tx } => match data {
-                HostMessage::DumpIr(code, opt) => {
-                    let mut interner = StringInterner::new();
-                    let output = match FunctionCompiler::compile_str(
-                        &mut interner,
-                        &code,
-                        shared_opt_to_dash_opt(opt),
-                    ) {
-                        Ok(v) => {
-                            decompile(&interner, &v.cp, &v.instructions).map_err(|x| x.to_string())
-                        }
-                        Err(err) => Err(err.formattable(&code, true).to_string()),
+                HostMessage::Handshake(host_version) => {
+                    if host_version != shared::PROTOCOL_VERSION {
+                        tracing::warn!(
+                            host_version,
+                            worker_version = shared::PROTOCOL_VERSION,
+                            "protocol version mismatch"
+                        );
+                    }
+
+                    let info = WorkerInfo {
+                        protocol_version: shared::PROTOCOL_VERSION,
+                        engine: "dash".into(),
+                        engine_version: env!("DASH_VERSION").into(),
+                        features: vec![
+                            Feature::Eval,
+                            Feature::DumpIr,
+                            Feature::Sessions,
+                            Feature::DumpAst,
+                            Feature::DumpTokens,
+                            Feature::DumpBytecode,
+                            Feature::DumpIrDiff,
+                            Feature::Bench,
+                            Feature::Stats,
+                            Feature::Wasm,
+                        ],
+                        opt_levels: vec![
+                            shared::Opt::None,
+                            shared::Opt::Basic,
+                            shared::Opt::Aggressive,
+                        ],
+                        engines: vec![EngineKind::Dash, EngineKind::Rhai, EngineKind::Boa],
                     };
 
+                    if tx.send(ClientMessage::Handshake(info)).is_err() {
+                        tracing::error!("failed to respond to job!")
+                    }
+                }
+                HostMessage::DumpIr(code, opt) => {
+                    let output = timed(|| dump::ir(&code, opt));
+
                     if tx.send(ClientMessage::EvalResponse(output)).is_err() {
                         tracing::error!("failed to respond to job!")
                     }
                 }
-                HostMessage::Eval(code, opt) => {
-                    let params = VmParams::default()
-                        .set_math_random_callback(|_| Ok(rand::random()))
-                        .set_time_millis_callback(|_| {
-                            Ok(SystemTime::now()
-                                .duration_since(SystemTime::UNIX_EPOCH)
-                                .unwrap()
-                                .as_millis()
-                                .try_into()
-                                .unwrap())
-                        });
-                    let mut vm = Vm::new(params);
-                    let scope = &mut vm.scope();
-                    let inspect = {
-                        const INSPECT_CODE: &str = include_str!("../js/inspect.js");
-                        let Value::Object(inspect) = scope
-                            .eval(INSPECT_CODE, shared_opt_to_dash_opt(opt))
-                            .unwrap()
-                            .root(scope)
-                        else {
-                            unreachable!()
-                        };
-
-                        Persistent::new(scope, inspect)
-                    };
+                HostMessage::DumpIrDiff(code, left, right) => {
+                    let output = timed(|| dump::ir_diff(&code, left, right));
+
+                    if tx.send(ClientMessage::EvalResponse(output)).is_err() {
+                        tracing::error!("failed to respond to job!")
+                    }
+                }
+                HostMessage::Bench(snippets, opts, budget) => {
+                    let results = bench::run(&snippets, &opts, budget);
+
+                    if tx.send(ClientMessage::BenchResponse(results)).is_err() {
+                        tracing::error!("failed to respond to job!")
+                    }
+                }
+                HostMessage::DumpAst(code) => {
+                    let output = timed(|| dump::ast(&code));
+
+                    if tx.send(ClientMessage::EvalResponse(output)).is_err() {
+                        tracing::error!("failed to respond to job!")
+                    }
+                }
+                HostMessage::DumpTokens(code) => {
+                    let output = timed(|| dump::tokens(&code));
 
-                    let output = match scope.eval(&code, shared_opt_to_dash_opt(opt)) {
-                        Ok(v) | Err(EvalError::Exception(v)) => {
-                            fmt_value(&inspect, v.root(scope), scope).map_err(|err| err.to_string())
+                    if tx.send(ClientMessage::EvalResponse(output)).is_err() {
+                        tracing::error!("failed to respond to job!")
+                    }
+                }
+                HostMessage::DumpBytecode(code, opt) => {
+                    let output = timed(|| dump::bytecode(&code, opt));
+
+                    if tx.send(ClientMessage::EvalResponse(output)).is_err() {
+                        tracing::error!("failed to respond to job!")
+                    }
+                }
+                HostMessage::Eval(kind, code, options) => {
+                    let output = crash::catch(|| {
+                        limits::run_limited(limits::MAX_HEAP_SIZE, || {
+                            engine::new(kind).eval(&code, options)
+                        })
+                    })
+                    .map_or_else(crash_output, |output| {
+                        output.unwrap_or_else(limit_exceeded_output)
+                    });
+
+                    if tx.send(ClientMessage::EvalResponse(output)).is_err() {
+                        tracing::error!("failed to respond to job!")
+                    }
+                }
+                HostMessage::EvalSession(id, code, options) => {
+                    let (session, new_session) = sessions.get_or_create(id);
+                    let output = crash::catch(|| {
+                        limits::run_limited(limits::MAX_HEAP_SIZE, || {
+                            session.engine.eval(&code, options)
+                        })
+                    });
+                    // After going over the heap limit the session holds on to too much memory,
+                    // and a crash leaves the VM in an unknown state, so start over next time
+                    let output = match output {
+                        Ok(Ok(output)) => output,
+                        Ok(Err(err)) => {
+                            sessions.reset(id);
+                            limit_exceeded_output(err)
                         }
-                        Err(EvalError::Middle(middle)) => {
-                            Err(middle.formattable(&code, true).to_string())
+                        Err(report) => {
+                            sessions.reset(id);
+                            crash_output(report)
                         }
                     };
 
+                    let response = ClientMessage::SessionEvalResponse {
+                        output,
+                        new_session,
+                    };
+                    if tx.send(response).is_err() {
+                        tracing::error!("failed to respond to job!")
+                    }
+                }
+                HostMessage::EvalWasm(code, function, args) => {
+                    let output = crash::catch(|| {
+                        limits::run_limited(limits::MAX_HEAP_SIZE, || {
+                            wasm::run(&code, &function, &args)
+                        })
+                    })
+                    .map_or_else(crash_output, |output| {
+                        output.unwrap_or_else(limit_exceeded_output)
+                    });
+
                     if tx.send(ClientMessage::EvalResponse(output)).is_err() {
                         tracing::error!("failed to respond to job!")
                     }
                 }
+                HostMessage::ResetSession(id) => {
+                    sessions.reset(id);
+
+                    if tx.send(ClientMessage::SessionReset).is_err() {
+                        tracing::error!("failed to respond to job!")
+                    }
+                }
             },
             Job::Unidirectional { .. } => unreachable!("there are no unidirectional messages"),
         }
     }
 
+    // the bot closed the connection, e.g. because it shut down this worker after being idle
     Ok(())
 }
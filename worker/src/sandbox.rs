@@ -0,0 +1,207 @@
+//! Locks down the worker process before it runs any untrusted code.
+//!
+//! The engines are not designed to contain hostile code, so on Linux the process additionally
+//! gets resource limits and a seccomp filter that only allows the system calls needed to talk to
+//! the bot over the already connected socket. Opening files, creating sockets and starting
+//! processes or threads all fail with `EPERM`, even if a bug in an engine lets code escape it.
+//!
+//! On other platforms this does nothing.
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::backtrace::Backtrace;
+    use std::collections::BTreeMap;
+    use std::io;
+    use std::time::Duration;
+
+    use anyhow::Context;
+    use seccompiler::BpfProgram;
+    use seccompiler::SeccompAction;
+    use seccompiler::SeccompCmpArgLen;
+    use seccompiler::SeccompCmpOp;
+    use seccompiler::SeccompCondition;
+    use seccompiler::SeccompFilter;
+    use seccompiler::SeccompRule;
+
+    /// Address space of the whole process. Generous, because the allocator reserves a lot more
+    /// address space than it uses
+    const MAX_ADDRESS_SPACE: u64 = 4 * 1024 * 1024 * 1024;
+    /// Open file descriptors. New ones can't be opened anyway, this is a second line of defense
+    const MAX_OPEN_FILES: u64 = 64;
+
+    /// System calls the worker needs after it is connected to the bot.
+    /// Everything else fails with `EPERM`
+    const ALLOWED_SYSCALLS: &[i64] = &[
+        // talking to the bot and logging to stderr
+        libc::SYS_read,
+        libc::SYS_write,
+        libc::SYS_readv,
+        libc::SYS_writev,
+        libc::SYS_recvfrom,
+        libc::SYS_sendto,
+        libc::SYS_recvmsg,
+        libc::SYS_sendmsg,
+        libc::SYS_close,
+        // the async runtime. `epoll_wait` only exists on x86_64, other architectures only have
+        // `epoll_pwait`, which is what newer libcs use everywhere
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_epoll_wait,
+        libc::SYS_epoll_pwait,
+        libc::SYS_epoll_ctl,
+        libc::SYS_futex,
+        libc::SYS_sched_yield,
+        libc::SYS_sched_getaffinity,
+        libc::SYS_nanosleep,
+        libc::SYS_clock_nanosleep,
+        libc::SYS_clock_gettime,
+        libc::SYS_gettid,
+        libc::SYS_getpid,
+        // memory
+        libc::SYS_mmap,
+        libc::SYS_munmap,
+        libc::SYS_mremap,
+        libc::SYS_mprotect,
+        libc::SYS_madvise,
+        libc::SYS_brk,
+        // randomness for seeding `Math.random` and hash maps
+        libc::SYS_getrandom,
+        // panics, signals and exiting
+        libc::SYS_rt_sigreturn,
+        libc::SYS_rt_sigprocmask,
+        libc::SYS_rt_sigaction,
+        libc::SYS_sigaltstack,
+        libc::SYS_tgkill,
+        libc::SYS_exit,
+        libc::SYS_exit_group,
+        // reading the CPU time for [`limit_cpu_time`]
+        libc::SYS_getrusage,
+        // `clone` and `clone3` are missing on purpose, which forbids new threads as well as new
+        // processes. The async runtime is single-threaded for that reason (see `main`), and the
+        // engines run on that thread too. A new thread would also escape the heap limit, which
+        // is tracked per thread. Threads can't be allowed on their own anyway, because the flags
+        // of `clone3` are behind a pointer seccomp can't look at
+    ];
+
+    fn set_limit(resource: libc::__rlimit_resource_t, soft: u64, hard: u64) -> anyhow::Result<()> {
+        let limit = libc::rlimit {
+            rlim_cur: soft,
+            rlim_max: hard,
+        };
+        // SAFETY: `limit` is a valid rlimit
+        if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    fn get_limit(resource: libc::__rlimit_resource_t) -> anyhow::Result<libc::rlimit> {
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        // SAFETY: `limit` is a valid rlimit to write to
+        if unsafe { libc::getrlimit(resource, &mut limit) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(limit)
+    }
+
+    fn apply_seccomp_filter() -> anyhow::Result<()> {
+        let mut rules: BTreeMap<i64, Vec<SeccompRule>> = ALLOWED_SYSCALLS
+            .iter()
+            .map(|&syscall| (syscall, Vec::new()))
+            .collect();
+
+        // setrlimit is implemented with prlimit64. Only allow it for our own CPU time limit,
+        // so that the other limits can't be lifted again
+        rules.insert(
+            libc::SYS_prlimit64,
+            vec![SeccompRule::new(vec![
+                SeccompCondition::new(0, SeccompCmpArgLen::Qword, SeccompCmpOp::Eq, 0)?,
+                SeccompCondition::new(
+                    1,
+                    SeccompCmpArgLen::Dword,
+                    SeccompCmpOp::Eq,
+                    libc::RLIMIT_CPU.into(),
+                )?,
+            ])?],
+        );
+
+        let filter = SeccompFilter::new(
+            rules,
+            SeccompAction::Errno(libc::EPERM as u32),
+            SeccompAction::Allow,
+            std::env::consts::ARCH
+                .try_into()
+                .context("seccomp is not supported on this architecture")?,
+        )?;
+        let program: BpfProgram = filter.try_into()?;
+
+        // in case a dependency started a thread of its own, restrict that one too
+        seccompiler::apply_filter_all_threads(&program)?;
+        Ok(())
+    }
+
+    pub fn enter() -> anyhow::Result<()> {
+        set_limit(libc::RLIMIT_AS, MAX_ADDRESS_SPACE, MAX_ADDRESS_SPACE)
+            .context("failed to limit the address space")?;
+        set_limit(libc::RLIMIT_NOFILE, MAX_OPEN_FILES, MAX_OPEN_FILES)
+            .context("failed to limit open files")?;
+        // no new processes. Threads count too, so this has to be the last thing before the filter.
+        // This is not what isolates the worker: the limit doesn't apply to root at all,
+        // and the seccomp filter forbids `clone` regardless
+        set_limit(libc::RLIMIT_NPROC, 0, 0).context("failed to forbid new processes")?;
+        set_limit(libc::RLIMIT_CORE, 0, 0).context("failed to disable core dumps")?;
+
+        // backtraces of crashes are symbolized by reading the worker's own binary,
+        // so do that once now, while files can still be opened, to get the debug info mapped
+        let _ = Backtrace::force_capture().to_string();
+
+        apply_seccomp_filter().context("failed to apply the seccomp filter")
+    }
+
+    pub fn limit_cpu_time(budget: Duration) -> anyhow::Result<()> {
+        // SAFETY: `usage` is a valid rusage to write to
+        let mut usage = unsafe { std::mem::zeroed::<libc::rusage>() };
+        if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        let used = (usage.ru_utime.tv_sec + usage.ru_stime.tv_sec) as u64;
+        let hard = get_limit(libc::RLIMIT_CPU)?.rlim_max;
+        // the soft limit sends SIGXCPU, which kills the process
+        set_limit(
+            libc::RLIMIT_CPU,
+            (used + budget.as_secs().max(1)).min(hard),
+            hard,
+        )
+    }
+}
+
+/// Applies the resource limits and the seccomp filter.
+/// Must be called after connecting to the bot, because no new connections can be made afterwards
+pub fn enter() -> anyhow::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        linux::enter()
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        tracing::warn!("sandboxing is only supported on Linux, running without it");
+        Ok(())
+    }
+}
+
+/// Limits how much CPU time the process may use from now on, until this is called again.
+/// Called before every job, so that a job that somehow outlives the host's timeout gets killed
+pub fn limit_cpu_time(budget: std::time::Duration) -> anyhow::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        linux::limit_cpu_time(budget)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = budget;
+        Ok(())
+    }
+}
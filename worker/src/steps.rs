@@ -0,0 +1,426 @@
+//! Step budget for JavaScript.
+//!
+//! Neither dash nor boa can be interrupted from the outside, so before evaluation the condition
+//! of every `while`, `do ... while` and `for (;;)` loop is rewritten to call `__step()` first.
+//! That function is a read-only global defined by `js/steps.js`, so user code can't replace it.
+//! It throws once the evaluation used up its budget, and every now and then checks the worker's
+//! other limits, so that e.g. going over the heap limit is noticed while a loop is still running.
+//!
+//! `for ... in` and `for ... of` loops are left alone, they only run forever if their iterator
+//! does, and that needs a loop of its own. The rewrite is based on tokens rather than a full parse,
+//! so if the rewritten code doesn't compile, the original code is evaluated instead.
+//! Code is only ever inserted within a line, so line numbers in errors stay the same.
+
+use shared::ErrorKind;
+use shared::EvalError;
+
+/// Loop iterations a single evaluation may run, including the timers it schedules
+pub const MAX_STEPS: u64 = 10_000_000;
+
+/// Name of the function that counts a step, see `js/steps.js`
+const STEP: &str = "__step()";
+
+/// The error reported for an evaluation that ran out of steps
+pub fn limit_exceeded() -> EvalError {
+    EvalError::new(
+        ErrorKind::LimitExceeded,
+        format!("step limit exceeded ({MAX_STEPS} loop iterations)"),
+    )
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TokenKind {
+    /// Identifiers and keywords
+    Word,
+    /// A single punctuation character
+    Punct,
+    /// Strings, templates, regular expressions and numbers
+    Literal,
+}
+
+struct Token<'a> {
+    kind: TokenKind,
+    text: &'a str,
+    start: usize,
+    end: usize,
+}
+
+/// Keywords after which a `/` starts a regular expression rather than being a division
+const REGEX_KEYWORDS: &[&str] = &[
+    "return",
+    "typeof",
+    "instanceof",
+    "in",
+    "of",
+    "new",
+    "delete",
+    "void",
+    "throw",
+    "case",
+    "do",
+    "else",
+    "yield",
+    "await",
+];
+
+fn regex_allowed(previous: Option<&Token<'_>>) -> bool {
+    match previous {
+        None => true,
+        Some(token) => match token.kind {
+            TokenKind::Word => REGEX_KEYWORDS.contains(&token.text),
+            TokenKind::Literal => false,
+            TokenKind::Punct => !matches!(token.text, ")" | "]"),
+        },
+    }
+}
+
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'$' || b >= 0x80
+}
+
+/// Skips a string literal starting at the quote at `start`
+fn skip_string(bytes: &[u8], start: usize) -> usize {
+    let quote = bytes[start];
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'\n' => return i,
+            b if b == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// Skips the text of a template literal starting at `start`, which is right after the opening
+/// backtick or the `}` closing a substitution. Returns where it ended and whether it stopped at
+/// the start of a substitution rather than at the closing backtick
+fn skip_template(bytes: &[u8], start: usize) -> (usize, bool) {
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'`' => return (i + 1, false),
+            b'$' if bytes.get(i + 1) == Some(&b'{') => return (i + 2, true),
+            _ => i += 1,
+        }
+    }
+    (bytes.len(), false)
+}
+
+/// Skips a regular expression literal starting at the slash at `start`, including its flags
+fn skip_regex(bytes: &[u8], start: usize) -> usize {
+    let mut i = start + 1;
+    let mut in_class = false;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'\n' => return i,
+            b'[' => {
+                in_class = true;
+                i += 1;
+            }
+            b']' => {
+                in_class = false;
+                i += 1;
+            }
+            b'/' if !in_class => {
+                i += 1;
+                while i < bytes.len() && is_word_byte(bytes[i]) {
+                    i += 1;
+                }
+                return i;
+            }
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+fn tokenize(code: &str) -> Vec<Token<'_>> {
+    let bytes = code.as_bytes();
+    let mut tokens: Vec<Token<'_>> = Vec::new();
+    // brace depth of every template substitution that is currently open
+    let mut substitutions = Vec::new();
+    let mut depth = 0usize;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let start = i;
+        let kind = match bytes[i] {
+            b if b.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = code[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |end| i + 2 + end + 2);
+                continue;
+            }
+            b'\'' | b'"' => {
+                i = skip_string(bytes, i);
+                TokenKind::Literal
+            }
+            b'`' => {
+                let (end, substitution) = skip_template(bytes, i + 1);
+                if substitution {
+                    substitutions.push(depth);
+                }
+                i = end;
+                TokenKind::Literal
+            }
+            b'}' if substitutions.last() == Some(&depth) => {
+                substitutions.pop();
+                let (end, substitution) = skip_template(bytes, i + 1);
+                if substitution {
+                    substitutions.push(depth);
+                }
+                i = end;
+                TokenKind::Literal
+            }
+            b'/' if regex_allowed(tokens.last()) => {
+                i = skip_regex(bytes, i);
+                TokenKind::Literal
+            }
+            b if b.is_ascii_digit() => {
+                while i < bytes.len() && (is_word_byte(bytes[i]) || bytes[i] == b'.') {
+                    i += 1;
+                }
+                TokenKind::Literal
+            }
+            b if is_word_byte(b) => {
+                while i < bytes.len() && is_word_byte(bytes[i]) {
+                    i += 1;
+                }
+                TokenKind::Word
+            }
+            b => {
+                match b {
+                    b'{' => depth += 1,
+                    b'}' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                i += 1;
+                TokenKind::Punct
+            }
+        };
+
+        // escapes at the very end of the code can skip past it
+        let end = i.min(bytes.len());
+        i = end;
+        tokens.push(Token {
+            kind,
+            // a multibyte character is only ever skipped as part of a word, string or comment,
+            // so this always ends on a character boundary
+            text: &code[start..end],
+            start,
+            end,
+        });
+    }
+
+    tokens
+}
+
+/// Index of the `)` matching the `(` at `open`
+fn matching_paren(tokens: &[Token<'_>], open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (index, token) in tokens.iter().enumerate().skip(open) {
+        match token.text {
+            "(" => depth += 1,
+            ")" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Indices of the semicolons between `open` and `close` that are not nested in any brackets
+fn top_level_semicolons(tokens: &[Token<'_>], open: usize, close: usize) -> Vec<usize> {
+    let mut depth = 0usize;
+    let mut semicolons = Vec::new();
+    for (index, token) in tokens.iter().enumerate().take(close).skip(open + 1) {
+        match token.text {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" => depth = depth.saturating_sub(1),
+            ";" if depth == 0 => semicolons.push(index),
+            _ => {}
+        }
+    }
+    semicolons
+}
+
+/// Rewrites the condition of every loop in `code` to count a step before it is evaluated
+pub fn instrument(code: &str) -> String {
+    let tokens = tokenize(code);
+    let mut insertions: Vec<(usize, String)> = Vec::new();
+
+    for (index, token) in tokens.iter().enumerate() {
+        if token.kind != TokenKind::Word || !matches!(token.text, "while" | "for") {
+            continue;
+        }
+        // a property that happens to be called like a keyword
+        if index > 0 && tokens[index - 1].text == "." {
+            continue;
+        }
+        // `for await` and anything that isn't a loop header
+        let open = index + 1;
+        if tokens.get(open).map(|token| token.text) != Some("(") {
+            continue;
+        }
+        let Some(close) = matching_paren(&tokens, open) else {
+            continue;
+        };
+
+        let (condition_start, condition_end) = if token.text == "while" {
+            (open, close)
+        } else {
+            match top_level_semicolons(&tokens, open, close)[..] {
+                [first, second] => (first, second),
+                // `for ... in` and `for ... of`
+                _ => continue,
+            }
+        };
+
+        if condition_end == condition_start + 1 {
+            // `for (;;)`
+            insertions.push((tokens[condition_start].end, format!(" {STEP}")));
+        } else {
+            insertions.push((tokens[condition_start].end, format!("{STEP} && (")));
+            insertions.push((tokens[condition_end].start, ")".into()));
+        }
+    }
+
+    insertions.sort_by_key(|&(position, _)| position);
+    let mut instrumented = String::with_capacity(code.len() + insertions.len() * 16);
+    let mut last = 0;
+    for (position, text) in insertions {
+        instrumented.push_str(&code[last..position]);
+        instrumented.push_str(&text);
+        last = position;
+    }
+    instrumented.push_str(&code[last..]);
+    instrumented
+}
+
+#[cfg(test)]
+mod tests {
+    use super::instrument;
+
+    #[test]
+    fn loops() {
+        assert_eq!(instrument("while (x) {}"), "while (__step() && (x)) {}");
+        assert_eq!(
+            instrument("do x++; while (x < 3)"),
+            "do x++; while (__step() && (x < 3))"
+        );
+        assert_eq!(instrument("for (;;) {}"), "for (; __step();) {}");
+        assert_eq!(
+            instrument("for (let i = 0; i < 3; i++) {}"),
+            "for (let i = 0;__step() && ( i < 3); i++) {}"
+        );
+        assert_eq!(
+            instrument("while (f(a, (b))) {}"),
+            "while (__step() && (f(a, (b)))) {}"
+        );
+    }
+
+    #[test]
+    fn not_loops() {
+        for code in [
+            "for (const x of xs) {}",
+            "for (const x in xs) {}",
+            "for await (const x of xs) {}",
+            "x.while(y); x.for(;;)",
+            "const whileLoop = 1, forever = 2",
+        ] {
+            assert_eq!(instrument(code), code);
+        }
+    }
+
+    #[test]
+    fn strings_and_comments() {
+        for code in [
+            "'while (x) {}'",
+            r#""for (;;) \" while (x)""#,
+            "// while (x) {}",
+            "/* for (;;) {} */",
+        ] {
+            assert_eq!(instrument(code), code);
+        }
+    }
+
+    #[test]
+    fn templates() {
+        assert_eq!(instrument("`while (x) {}`"), "`while (x) {}`");
+        assert_eq!(
+            instrument("`${a} while (x) ${b} for (;;)`"),
+            "`${a} while (x) ${b} for (;;)`"
+        );
+        // substitutions are code, and can contain braces and templates of their own
+        assert_eq!(
+            instrument(
+                "`${(() => { while (x) {} })()} ${`${{ a: 1 }.a}`} while (y)`; while (z) {}"
+            ),
+            "`${(() => { while (__step() && (x)) {} })()} ${`${{ a: 1 }.a}`} while (y)`; \
+             while (__step() && (z)) {}"
+        );
+    }
+
+    #[test]
+    fn regexes() {
+        assert_eq!(instrument("/while (x)/.test(s)"), "/while (x)/.test(s)");
+        assert_eq!(instrument("x = /[/]while (x)/g"), "x = /[/]while (x)/g");
+        assert_eq!(
+            instrument("if (/for (;;)/.test(s)) {}"),
+            "if (/for (;;)/.test(s)) {}"
+        );
+        // divisions are not regexes
+        assert_eq!(
+            instrument("let r = a / b; while (c) {} let s = (d) / e"),
+            "let r = a / b; while (__step() && (c)) {} let s = (d) / e"
+        );
+        assert_eq!(
+            instrument("x = a[0] / 2; while (c) {} x /= 2"),
+            "x = a[0] / 2; while (__step() && (c)) {} x /= 2"
+        );
+    }
+
+    #[test]
+    fn automatic_semicolons() {
+        let code = "let i = 0\nwhile (i < 3) i++\nfor (;;) break\nlet r = i\n/ 2\nwhile (r) r--";
+        let instrumented = instrument(code);
+        assert_eq!(
+            instrumented,
+            "let i = 0\nwhile (__step() && (i < 3)) i++\nfor (; __step();) break\nlet r = i\n/ 2\n\
+             while (__step() && (r)) r--"
+        );
+        // code is only inserted within lines, so line numbers in errors stay the same
+        assert_eq!(instrumented.lines().count(), code.lines().count());
+    }
+
+    #[test]
+    fn unterminated() {
+        for code in [
+            "while (",
+            "'while (x) {}",
+            "`${while (x) {}",
+            "/while (x) {}",
+            "a\\",
+        ] {
+            instrument(code);
+        }
+    }
+}
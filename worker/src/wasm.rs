@@ -0,0 +1,313 @@
+//! WebAssembly modules, run in the wasmi interpreter.
+//!
+//! Unlike the other languages there is no completion value to show, so a module is instantiated
+//! and then one of its exported functions is called with arguments given by the user.
+
+use std::fmt::Write;
+use std::ops::Range;
+use std::time::Instant;
+
+use base64::Engine as _;
+use shared::ErrorKind;
+use shared::EvalError;
+use shared::EvalOutput;
+use wasmi::core::TrapCode;
+use wasmi::core::ValueType;
+use wasmi::core::F32;
+use wasmi::core::F64;
+use wasmi::Config;
+use wasmi::Engine;
+use wasmi::Linker;
+use wasmi::Module;
+use wasmi::Store;
+use wasmi::Value;
+
+/// Fuel for instantiating the module and running the call.
+/// Every executed instruction uses up roughly one unit
+const MAX_FUEL: u64 = 100_000_000;
+/// Size of a memory in 64 KiB pages, 16 MiB in total. Well below the worker's heap limit,
+/// which is only noticed once the call returned
+const MAX_MEMORY_PAGES: u32 = 256;
+const MEMORY_SECTION_ID: u8 = 5;
+
+/// Accepts both the text format and base64 encoded binaries
+fn decode(code: &str) -> Result<Vec<u8>, EvalError> {
+    // WAT is never valid base64 because of its parentheses, so try that first
+    let compact = code.split_ascii_whitespace().collect::<String>();
+    if let Ok(bytes) = base64::engine::general_purpose::STANDARD.decode(compact) {
+        return Ok(bytes);
+    }
+
+    wat::parse_str(code).map_err(|err| EvalError::new(ErrorKind::Syntax, err.to_string()))
+}
+
+fn read_leb128(bytes: &[u8], pos: &mut usize) -> Option<u32> {
+    let mut value = 0u32;
+    for shift in (0..35).step_by(7) {
+        let byte = *bytes.get(*pos)?;
+        *pos += 1;
+        value |= u32::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn write_leb128(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Range of the whole section with the given ID, and of its contents
+fn find_section(bytes: &[u8], id: u8) -> Option<(Range<usize>, Range<usize>)> {
+    // after the magic number and the version
+    let mut pos = 8;
+    while pos < bytes.len() {
+        let start = pos;
+        let section_id = bytes[pos];
+        pos += 1;
+        let len = read_leb128(bytes, &mut pos)? as usize;
+        let contents = pos..pos.checked_add(len).filter(|&end| end <= bytes.len())?;
+        if section_id == id {
+            return Some((start..contents.end, contents));
+        }
+        pos = contents.end;
+    }
+    None
+}
+
+/// The minimum and maximum size of every memory in the contents of a memory section
+fn parse_memories(contents: &[u8]) -> Option<Vec<(u32, Option<u32>)>> {
+    let mut pos = 0;
+    let count = read_leb128(contents, &mut pos)?;
+    let mut memories = Vec::new();
+    for _ in 0..count {
+        let flags = *contents.get(pos)?;
+        pos += 1;
+        let min = read_leb128(contents, &mut pos)?;
+        let max = match flags {
+            0 => None,
+            1 => Some(read_leb128(contents, &mut pos)?),
+            // shared and 64-bit memories, which wasmi doesn't support anyway
+            _ => return None,
+        };
+        memories.push((min, max));
+    }
+    (pos == contents.len()).then_some(memories)
+}
+
+/// Gives every memory of the module a maximum size of at most [`MAX_MEMORY_PAGES`], so that
+/// `memory.grow` fails beyond that like it does for any other maximum.
+/// This version of wasmi has no resource limiter, so the module's memory section is rewritten.
+/// Modules that don't decode are left alone, so that wasmi reports the error
+fn limit_memories(bytes: &mut Vec<u8>) -> Result<(), EvalError> {
+    let Some((section, contents)) = find_section(bytes, MEMORY_SECTION_ID) else {
+        return Ok(());
+    };
+    let Some(memories) = parse_memories(&bytes[contents]) else {
+        return Ok(());
+    };
+
+    let mut limited = Vec::new();
+    write_leb128(&mut limited, memories.len() as u32);
+    for (min, max) in memories {
+        if min > MAX_MEMORY_PAGES {
+            return Err(EvalError::new(
+                ErrorKind::LimitExceeded,
+                format!(
+                    "the module's memory starts with {min} pages, but at most {MAX_MEMORY_PAGES} \
+                     pages (64 KiB each) are allowed"
+                ),
+            ));
+        }
+        limited.push(1);
+        write_leb128(&mut limited, min);
+        write_leb128(
+            &mut limited,
+            max.map_or(MAX_MEMORY_PAGES, |max| max.min(MAX_MEMORY_PAGES)),
+        );
+    }
+
+    let mut replacement = vec![MEMORY_SECTION_ID];
+    write_leb128(&mut replacement, limited.len() as u32);
+    replacement.extend(limited);
+    bytes.splice(section, replacement);
+    Ok(())
+}
+
+fn parse_arg(arg: &str, ty: ValueType) -> Option<Value> {
+    // unsigned values are reinterpreted, so that e.g. 4294967295 can be passed as an i32 (-1)
+    let value = match ty {
+        ValueType::I32 => Value::I32(
+            arg.parse::<i32>()
+                .ok()
+                .or_else(|| arg.parse::<u32>().ok().map(|n| n as i32))?,
+        ),
+        ValueType::I64 => Value::I64(
+            arg.parse::<i64>()
+                .ok()
+                .or_else(|| arg.parse::<u64>().ok().map(|n| n as i64))?,
+        ),
+        ValueType::F32 => Value::F32(F32::from(arg.parse::<f32>().ok()?)),
+        ValueType::F64 => Value::F64(F64::from(arg.parse::<f64>().ok()?)),
+        ValueType::FuncRef | ValueType::ExternRef => return None,
+    };
+    Some(value)
+}
+
+fn fmt_value(value: &Value) -> String {
+    match value {
+        Value::I32(n) => format!("{n}: i32"),
+        Value::I64(n) => format!("{n}: i64"),
+        Value::F32(n) => format!("{:?}: f32", f32::from(*n)),
+        Value::F64(n) => format!("{:?}: f64", f64::from(*n)),
+        Value::FuncRef(func) if func.is_null() => "null: funcref".into(),
+        Value::FuncRef(_) => "funcref".into(),
+        Value::ExternRef(_) => "externref".into(),
+    }
+}
+
+fn exception(message: impl Into<String>) -> EvalError {
+    EvalError::new(ErrorKind::Exception, message)
+}
+
+fn trap_error(err: wasmi::Error) -> EvalError {
+    match err {
+        wasmi::Error::Trap(trap) if matches!(trap.trap_code(), Some(TrapCode::OutOfFuel)) => {
+            EvalError::new(
+                ErrorKind::LimitExceeded,
+                format!("ran out of fuel after {MAX_FUEL} units"),
+            )
+        }
+        wasmi::Error::Trap(trap) => exception(format!("trap: {trap}")),
+        other => exception(other.to_string()),
+    }
+}
+
+/// Decodes, validates and instantiates the module, then calls the exported `function`.
+/// Imports are not supported
+fn call(code: &str, function: &str, args: &[String]) -> Result<String, EvalError> {
+    let mut bytes = decode(code)?;
+    limit_memories(&mut bytes)?;
+
+    let mut config = Config::default();
+    config.consume_fuel(true);
+    let engine = Engine::new(&config);
+    let module = Module::new(&engine, &bytes[..])
+        .map_err(|err| EvalError::new(ErrorKind::Syntax, err.to_string()))?;
+
+    let mut store = Store::new(&engine, ());
+    store
+        .add_fuel(MAX_FUEL)
+        .map_err(|err| EvalError::new(ErrorKind::Internal, err.to_string()))?;
+    let instance = Linker::<()>::new(&engine)
+        .instantiate(&mut store, &module)
+        .map_err(trap_error)?
+        .start(&mut store)
+        .map_err(trap_error)?;
+
+    let func = instance.get_func(&store, function).ok_or_else(|| {
+        let exports = module
+            .exports()
+            .filter(|export| export.ty().func().is_some())
+            .map(|export| export.name())
+            .collect::<Vec<_>>();
+        if exports.is_empty() {
+            exception("the module doesn't export any functions")
+        } else {
+            exception(format!(
+                "the module has no exported function `{function}`. Exported functions: {}",
+                exports.join(", ")
+            ))
+        }
+    })?;
+
+    let ty = func.ty(&store);
+    if ty.params().len() != args.len() {
+        return Err(exception(format!(
+            "`{function}` takes {} arguments ({:?}), but {} were given",
+            ty.params().len(),
+            ty.params(),
+            args.len()
+        )));
+    }
+    let params = args
+        .iter()
+        .zip(ty.params())
+        .map(|(arg, &ty)| {
+            parse_arg(arg, ty).ok_or_else(|| exception(format!("`{arg}` is not a valid {ty:?}")))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut results = ty
+        .results()
+        .iter()
+        .map(|&ty| Value::default(ty))
+        .collect::<Vec<_>>();
+    func.call(&mut store, &params, &mut results)
+        .map_err(trap_error)?;
+
+    let mut output = String::new();
+    for result in &results {
+        let _ = writeln!(output, "{}", fmt_value(result));
+    }
+    if output.is_empty() {
+        output.push_str("no results");
+    }
+    Ok(output)
+}
+
+pub fn run(code: &str, function: &str, args: &[String]) -> EvalOutput {
+    let start = Instant::now();
+    let result = call(code, function, args);
+
+    EvalOutput {
+        result,
+        logs: String::new(),
+        duration: start.elapsed(),
+        stats: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::call;
+    use super::MAX_MEMORY_PAGES;
+
+    const GROW: &str = r#"(module
+        (memory 1)
+        (func (export "grow") (param i32) (result i32) (memory.grow (local.get 0))))"#;
+
+    #[test]
+    fn memory_is_limited() {
+        let grow = |pages: u32| call(GROW, "grow", &[pages.to_string()]).unwrap();
+        assert_eq!(grow(MAX_MEMORY_PAGES - 1), "1: i32\n");
+        assert_eq!(grow(MAX_MEMORY_PAGES), "-1: i32\n");
+        assert_eq!(grow(65536), "-1: i32\n");
+
+        let lower = GROW.replace("(memory 1)", "(memory 1 2)");
+        assert_eq!(call(&lower, "grow", &["2".into()]).unwrap(), "-1: i32\n");
+        assert_eq!(call(&lower, "grow", &["1".into()]).unwrap(), "1: i32\n");
+
+        let too_big = GROW.replace("(memory 1)", "(memory 1000)");
+        assert!(call(&too_big, "grow", &["0".into()]).is_err());
+    }
+
+    #[test]
+    fn modules_without_memory() {
+        let add = r#"(module (func (export "add") (param i32 i32) (result i32)
+            (i32.add (local.get 0) (local.get 1))))"#;
+        assert_eq!(
+            call(add, "add", &["1".into(), "2".into()]).unwrap(),
+            "3: i32\n"
+        );
+    }
+}